@@ -0,0 +1,9 @@
+-- Human readable details of a level version, shown in the editor's level
+-- browser. A version without a row simply has no metadata yet.
+CREATE TABLE IF NOT EXISTS level_metadata (
+    version VARCHAR(32) PRIMARY KEY,
+    name TEXT NOT NULL DEFAULT '',
+    author TEXT NOT NULL DEFAULT '',
+    description TEXT NOT NULL DEFAULT '',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
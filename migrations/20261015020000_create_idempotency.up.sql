@@ -0,0 +1,11 @@
+-- Results of `/set-object` calls made with an `Idempotency-Key` header, so a
+-- retried request returns the first result instead of inserting again. Rows
+-- older than IDEMPOTENCY_TTL_SECS are deleted by the server.
+CREATE TABLE IF NOT EXISTS idempotency (
+    key TEXT PRIMARY KEY,
+    object_id INTEGER,
+    response JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_created_at ON idempotency (created_at);
@@ -0,0 +1,10 @@
+-- Only the default prefix's rows fit the old single-column keys.
+DELETE FROM level_metadata WHERE table_prefix <> 'objects_v';
+ALTER TABLE level_metadata DROP CONSTRAINT level_metadata_pkey;
+ALTER TABLE level_metadata DROP COLUMN table_prefix;
+ALTER TABLE level_metadata ADD PRIMARY KEY (version);
+
+DELETE FROM idempotency WHERE table_prefix <> 'objects_v';
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency DROP COLUMN table_prefix;
+ALTER TABLE idempotency ADD PRIMARY KEY (key);
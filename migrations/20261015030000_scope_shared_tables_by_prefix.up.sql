@@ -0,0 +1,12 @@
+-- Deployments sharing a database through TABLE_PREFIX keep their own level
+-- metadata and idempotency keys. Rows from before belong to the default
+-- prefix.
+ALTER TABLE level_metadata ADD COLUMN table_prefix TEXT NOT NULL DEFAULT 'objects_v';
+ALTER TABLE level_metadata ALTER COLUMN table_prefix DROP DEFAULT;
+ALTER TABLE level_metadata DROP CONSTRAINT level_metadata_pkey;
+ALTER TABLE level_metadata ADD PRIMARY KEY (table_prefix, version);
+
+ALTER TABLE idempotency ADD COLUMN table_prefix TEXT NOT NULL DEFAULT 'objects_v';
+ALTER TABLE idempotency ALTER COLUMN table_prefix DROP DEFAULT;
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency ADD PRIMARY KEY (table_prefix, key);
@@ -0,0 +1,243 @@
+//! Request and response bodies of the HTTP API, shared by the server and the
+//! `client` module.
+//!
+//! Every JSON field and enum tag is snake_case, the same as the Rust names, so
+//! no `rename_all` is needed. Unity deserializes by name, so renaming a field
+//! here breaks clients; add a `#[serde(rename)]` instead.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgRow, prelude::FromRow, types::Json as SqlJson, Row};
+use utoipa::ToSchema;
+
+use crate::geometry::{Collider, Quat, Vec3};
+
+/// Body of every error response.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Same as the `X-Request-Id` header, so it can be quoted in bug reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Every invalid field of the request, when validation failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// One invalid request field. Fields inside a batch are named by their path,
+/// e.g. `objects[3].scale`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> FieldError {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CountResponse {
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ListVersionsResponse {
+    pub versions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeleteObjectResponse {
+    pub rows_affected: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LevelObject {
+    pub id: i32,
+    pub object_type: String,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    pub collider: Option<Collider>,
+    pub tags: Vec<String>,
+    pub rev: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Reads the JSONB collider. A stored value that doesn't match `Collider`
+/// (hand edited, or converted from a legacy text column) reads as no
+/// collider rather than failing the whole query.
+fn decode_collider(row: &PgRow) -> Result<Option<Collider>, sqlx::Error> {
+    let value: Option<SqlJson<serde_json::Value>> = row.try_get("collider")?;
+    Ok(value.and_then(|SqlJson(value)| {
+        serde_json::from_value(value)
+            .map_err(|e| tracing::warn!("ignoring unreadable collider: {}", e))
+            .ok()
+    }))
+}
+
+impl FromRow<'_, PgRow> for LevelObject {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(LevelObject {
+            id: row.try_get("id")?,
+            object_type: row.try_get("object_type")?,
+            position: Vec3 {
+                x: row.try_get("pos_x")?,
+                y: row.try_get("pos_y")?,
+                z: row.try_get("pos_z")?,
+            },
+            rotation: Quat {
+                x: row.try_get("rot_x")?,
+                y: row.try_get("rot_y")?,
+                z: row.try_get("rot_z")?,
+                w: row.try_get("rot_w")?,
+            },
+            scale: Vec3 {
+                x: row.try_get("scl_x")?,
+                y: row.try_get("scl_y")?,
+                z: row.try_get("scl_z")?,
+            },
+            collider: decode_collider(row)?,
+            tags: row.try_get("tags")?,
+            rev: row.try_get("rev")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GetObjectsResponse {
+    pub objects: Vec<LevelObject>,
+    pub total: i64,
+    /// Pass as `cursor` to get the next page; absent on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetObjectByIdResponse {
+    pub object: LevelObject,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SetLevelObjectRequest {
+    pub version: String,
+    /// Overwrites the object with this id instead of adding a new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    /// The server's default type (`prop` unless configured) when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_type: Option<String>,
+    pub position: String,
+    pub rotation: String,
+    pub scale: String,
+    pub collider: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Same as for `/update-object`; only checked when overwriting by `id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+}
+
+/// A level object as uploaded in a batch, where the version is shared by the
+/// whole request.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NewLevelObject {
+    pub object_type: String,
+    pub position: String,
+    pub rotation: String,
+    pub scale: String,
+    pub collider: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl From<LevelObject> for NewLevelObject {
+    fn from(object: LevelObject) -> Self {
+        NewLevelObject {
+            object_type: object.object_type,
+            position: object.position.to_string(),
+            rotation: object.rotation.to_string(),
+            scale: object.scale.to_string(),
+            collider: serde_json::to_string(&object.collider)
+                .expect("a collider always serializes"),
+            tags: object.tags,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SetLevelObjectsRequest {
+    pub version: String,
+    pub objects: Vec<NewLevelObject>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateLevelObjectRequest {
+    pub version: String,
+    pub id: i32,
+    pub object_type: String,
+    pub position: String,
+    pub rotation: String,
+    pub scale: String,
+    pub collider: String,
+    /// Replaces the tags when given, otherwise they are kept.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// The `rev` the client last saw. When given, the update is refused with
+    /// a 409 if someone else has changed the object since.
+    #[serde(default)]
+    pub rev: Option<i32>,
+    /// Who is editing. The update is refused with a 423 while another
+    /// editor holds a lock on the object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+}
+
+/// Changes only the fields that are present; the rest of the object is kept.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct PatchLevelObjectRequest {
+    pub version: String,
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<String>,
+    /// Collider JSON; `"null"` removes the collider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Same as for `/update-object`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<i32>,
+    /// Same as for `/update-object`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SetObjectsResponse {
+    pub count: i64,
+    pub success: bool,
+    /// Only set by `set-object`: the id of the stored object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    /// Only set by `set-object`: the object as stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object: Option<LevelObject>,
+    /// Only set by `prepare`: rows it deleted, including soft deleted ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_count: Option<u64>,
+}
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::error::AppError;
+
+/// Header clients send the shared secret in.
+const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Clone)]
+pub struct ApiKey(Arc<String>);
+
+impl ApiKey {
+    pub fn new(key: String) -> Self {
+        ApiKey(Arc::new(key))
+    }
+
+    /// Compares in constant time so the key can't be guessed byte by byte
+    /// from response timings.
+    fn matches(&self, candidate: &[u8]) -> bool {
+        let expected = self.0.as_bytes();
+        if expected.len() != candidate.len() {
+            return false;
+        }
+        expected
+            .iter()
+            .zip(candidate)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+/// Rejects requests whose `X-API-Key` header doesn't match the configured key.
+pub async fn require_api_key(
+    State(api_key): State<ApiKey>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    match request.headers().get(API_KEY_HEADER) {
+        Some(value) if api_key.matches(value.as_bytes()) => Ok(next.run(request).await),
+        Some(_) => Err(AppError::Unauthorized("invalid API key".to_string())),
+        None => Err(AppError::Unauthorized(
+            "missing X-API-Key header".to_string(),
+        )),
+    }
+}
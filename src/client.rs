@@ -0,0 +1,224 @@
+//! Typed client for the level server's HTTP API.
+
+use std::fmt;
+
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::api::{
+    CountResponse, DeleteObjectResponse, ErrorResponse, GetObjectsResponse, LevelObject,
+    ListVersionsResponse, PatchLevelObjectRequest, SetLevelObjectRequest, SetLevelObjectsRequest,
+    SetObjectsResponse, UpdateLevelObjectRequest,
+};
+
+/// Page size used when reading a whole level, the most the server allows.
+const PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request didn't complete or the body couldn't be decoded.
+    Http(reqwest::Error),
+    /// The server answered with an error status.
+    Api {
+        status: StatusCode,
+        error: ErrorResponse,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "request to level server failed: {}", e),
+            ClientError::Api { status, error } => {
+                write!(f, "level server returned {}: {}", status, error.error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Client {
+    /// `base_url` is where the server is reachable, e.g.
+    /// `http://127.0.0.1:3000`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Sends `key` as `x-api-key`, which the server requires for writes when
+    /// `API_KEY` is set.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Every object in the level, read page by page.
+    pub async fn get_objects(&self, version: &str) -> Result<Vec<LevelObject>, ClientError> {
+        let mut objects = Vec::new();
+        let mut cursor: Option<i32> = None;
+        loop {
+            let mut query = vec![
+                ("version", version.to_string()),
+                ("limit", PAGE_SIZE.to_string()),
+            ];
+            if let Some(cursor) = cursor {
+                query.push(("cursor", cursor.to_string()));
+            }
+            let page: GetObjectsResponse = read(
+                self.request(reqwest::Method::GET, "/get-objects")
+                    .query(&query)
+                    .send()
+                    .await?,
+            )
+            .await?;
+            objects.extend(page.objects);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    pub async fn get_object(&self, version: &str, id: i32) -> Result<LevelObject, ClientError> {
+        read(
+            self.request(reqwest::Method::GET, "/get-object")
+                .query(&[("version", version), ("id", &id.to_string())])
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn count(&self, version: &str) -> Result<i64, ClientError> {
+        let response: CountResponse = read(
+            self.request(reqwest::Method::GET, "/count")
+                .query(&[("version", version)])
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(response.count)
+    }
+
+    pub async fn versions(&self) -> Result<Vec<String>, ClientError> {
+        let response: ListVersionsResponse = read(
+            self.request(reqwest::Method::GET, "/versions")
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(response.versions)
+    }
+
+    pub async fn set_object(
+        &self,
+        req: &SetLevelObjectRequest,
+    ) -> Result<SetObjectsResponse, ClientError> {
+        read(
+            self.request(reqwest::Method::POST, "/set-object")
+                .json(req)
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn set_objects(
+        &self,
+        req: &SetLevelObjectsRequest,
+    ) -> Result<SetObjectsResponse, ClientError> {
+        read(
+            self.request(reqwest::Method::POST, "/set-objects")
+                .json(req)
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn update_object(
+        &self,
+        req: &UpdateLevelObjectRequest,
+    ) -> Result<LevelObject, ClientError> {
+        read(
+            self.request(reqwest::Method::POST, "/update-object")
+                .json(req)
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    /// Changes only the fields set in `req`.
+    pub async fn patch_object(
+        &self,
+        req: &PatchLevelObjectRequest,
+    ) -> Result<LevelObject, ClientError> {
+        read(
+            self.request(reqwest::Method::POST, "/patch-object")
+                .json(req)
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    /// Soft deletes the object; `/restore-object` can bring it back.
+    pub async fn delete_object(
+        &self,
+        version: &str,
+        id: i32,
+    ) -> Result<DeleteObjectResponse, ClientError> {
+        read(
+            self.request(reqwest::Method::DELETE, "/delete-object")
+                .query(&[("version", version), ("id", &id.to_string())])
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.header("x-api-key", key),
+            None => request,
+        }
+    }
+}
+
+/// Decodes a successful response as `T`, or the server's error body.
+async fn read<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json().await?);
+    }
+    let error = response
+        .json::<ErrorResponse>()
+        .await
+        .unwrap_or_else(|_| ErrorResponse {
+            error: status.to_string(),
+            request_id: None,
+            errors: Vec::new(),
+        });
+    Err(ClientError::Api { status, error })
+}
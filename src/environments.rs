@@ -0,0 +1,124 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{error::AppError, events::Events};
+
+/// One database the server serves levels from, together with the live
+/// updates of its levels.
+#[derive(Clone)]
+pub struct Environment {
+    pub pool: PgPool,
+    pub events: Events,
+    pub upgraded: UpgradedTables,
+}
+
+impl Environment {
+    fn new(pool: PgPool) -> Self {
+        Environment {
+            pool,
+            events: Events::new(),
+            upgraded: UpgradedTables::default(),
+        }
+    }
+}
+
+/// Versions whose level table is known to have the current columns, so the
+/// upgrade only runs the first time a level is written after startup.
+#[derive(Clone, Default)]
+pub struct UpgradedTables(Arc<Mutex<HashSet<String>>>);
+
+impl UpgradedTables {
+    pub fn contains(&self, version: &str) -> bool {
+        self.0.lock().unwrap().contains(version)
+    }
+
+    pub fn insert(&self, version: String) {
+        self.0.lock().unwrap().insert(version);
+    }
+
+    /// Forgets a dropped level, whose version may later name a table made
+    /// by an older release.
+    pub fn remove(&self, version: &str) {
+        self.0.lock().unwrap().remove(version);
+    }
+}
+
+/// The primary database from `DATABASE_URL` plus the named ones from
+/// `DATABASE_URL_{NAME}`, selected per request with `?env=name`.
+#[derive(Clone)]
+pub struct Environments {
+    primary: Environment,
+    named: Arc<BTreeMap<String, Environment>>,
+}
+
+impl Environments {
+    pub fn new(primary: PgPool, named: BTreeMap<String, PgPool>) -> Self {
+        Environments {
+            primary: Environment::new(primary),
+            named: Arc::new(
+                named
+                    .into_iter()
+                    .map(|(name, pool)| (name, Environment::new(pool)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The named environment, or the primary one when `name` is `None`.
+    pub fn get(&self, name: Option<&str>) -> Result<&Environment, AppError> {
+        match name {
+            None => Ok(&self.primary),
+            Some(name) => self.named.get(name).ok_or_else(|| {
+                AppError::Validation(format!(
+                    "unknown env {:?}, expected one of: {}",
+                    name,
+                    self.named.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))
+            }),
+        }
+    }
+
+    /// The pool of every environment, the primary one first.
+    pub fn pools(&self) -> impl Iterator<Item = &PgPool> {
+        self.all().map(|e| &e.pool)
+    }
+
+    /// Every environment, the primary one first.
+    pub fn all(&self) -> impl Iterator<Item = &Environment> {
+        std::iter::once(&self.primary).chain(self.named.values())
+    }
+}
+
+#[derive(Deserialize)]
+struct EnvParams {
+    env: Option<String>,
+}
+
+/// Picks the environment from the request's `env` query parameter, which
+/// every endpoint accepts next to its own parameters.
+#[async_trait]
+impl<S> FromRequestParts<S> for Environment
+where
+    Environments: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<EnvParams>::try_from_uri(&parts.uri)
+            .map_err(|e| AppError::Validation(e.body_text()))?;
+        Environments::from_ref(state)
+            .get(params.env.as_deref())
+            .cloned()
+    }
+}
@@ -0,0 +1,172 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+
+use level_server::{
+    api::{ErrorResponse, FieldError},
+    queries::{table_prefix, validate_numeric_version, VersionError},
+};
+
+use crate::request_id;
+
+/// Error returned by every handler. Each variant maps onto a status code and
+/// is rendered as `{"error": "...", "request_id": "..."}`, plus `errors` for
+/// invalid fields.
+#[derive(Debug)]
+pub enum AppError {
+    Db(sqlx::Error),
+    NotFound(String),
+    BadVersion(VersionError),
+    Validation(String),
+    /// Rendered with the offending fields listed under `errors`.
+    InvalidFields(Vec<FieldError>),
+    /// The body doesn't match the object schema; fields are JSON paths.
+    SchemaViolation(Vec<FieldError>),
+    Unauthorized(String),
+    Conflict(String),
+    /// Another editor holds a lock on the object.
+    Locked(String),
+    TooManyRequests(String),
+    /// Writes are switched off with `READ_ONLY`.
+    ServiceUnavailable(String),
+    /// The level already holds `MAX_OBJECTS_PER_LEVEL` objects.
+    InsufficientStorage(String),
+    Internal(String),
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::Db(sqlx::Error::RowNotFound) => StatusCode::NOT_FOUND,
+            AppError::Db(e) if is_query_timeout(e) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Db(e) if missing_level(e).is_some() => StatusCode::NOT_FOUND,
+            AppError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadVersion(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            AppError::SchemaViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Locked(_) => StatusCode::LOCKED,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::InsufficientStorage(_) => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::Db(sqlx::Error::RowNotFound) => "not found".to_string(),
+            AppError::Db(e) if is_query_timeout(e) => "query timed out".to_string(),
+            AppError::Db(e) => match missing_level(e) {
+                Some(version) => format!("level version {} does not exist", version),
+                None => e.to_string(),
+            },
+            AppError::NotFound(msg) => msg.clone(),
+            AppError::BadVersion(e) => e.to_string(),
+            AppError::Validation(msg) => msg.clone(),
+            AppError::InvalidFields(errors) => errors
+                .iter()
+                .map(|e| format!("invalid {}: {}", e.field, e.message))
+                .collect::<Vec<_>>()
+                .join("; "),
+            AppError::SchemaViolation(errors) => format!(
+                "request doesn't match the object schema: {}",
+                errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+            AppError::Unauthorized(msg) => msg.clone(),
+            AppError::Conflict(msg) => msg.clone(),
+            AppError::Locked(msg) => msg.clone(),
+            AppError::TooManyRequests(msg) => msg.clone(),
+            AppError::ServiceUnavailable(msg) => msg.clone(),
+            AppError::InsufficientStorage(msg) => msg.clone(),
+            AppError::Internal(msg) => msg.clone(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!("{}", self.message());
+        }
+        let error = self.message();
+        let errors = match self {
+            AppError::InvalidFields(errors) | AppError::SchemaViolation(errors) => errors,
+            _ => Vec::new(),
+        };
+        let body = ErrorResponse {
+            error,
+            request_id: request_id::current(),
+            errors,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Db(err)
+    }
+}
+
+impl From<FieldError> for AppError {
+    fn from(err: FieldError) -> Self {
+        AppError::InvalidFields(vec![err])
+    }
+}
+
+impl From<VersionError> for AppError {
+    fn from(err: VersionError) -> Self {
+        AppError::BadVersion(err)
+    }
+}
+
+/// Whether the database rejected a query because the table doesn't exist,
+/// i.e. the level version was never prepared.
+pub fn is_undefined_table(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db_err) => db_err.code().as_deref() == Some("42P01"),
+        _ => false,
+    }
+}
+
+/// The version whose level table a query failed to find, if the error is a
+/// missing `objects_v{version}` table or one of its `_history` and
+/// `_snapshots` tables. Other missing tables aren't the client's fault.
+fn missing_level(err: &sqlx::Error) -> Option<String> {
+    let sqlx::Error::Database(db_err) = err else {
+        return None;
+    };
+    if db_err.code().as_deref() != Some("42P01") {
+        return None;
+    }
+    // Postgres names the relation in quotes: `relation "objects_v7" does
+    // not exist`.
+    let table = db_err.message().split('"').nth(1)?;
+    let table = table.rsplit('.').next()?;
+    let version = table.strip_prefix(table_prefix())?;
+    let version = version
+        .strip_suffix("_history")
+        .or_else(|| version.strip_suffix("_snapshots"))
+        .unwrap_or(version);
+    validate_numeric_version(version).ok()?;
+    Some(version.to_string())
+}
+
+/// Whether Postgres cancelled the query, which is how `statement_timeout`
+/// ends a statement that ran too long.
+fn is_query_timeout(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db_err) => db_err.code().as_deref() == Some("57014"),
+        _ => false,
+    }
+}
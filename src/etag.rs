@@ -0,0 +1,106 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{
+    error::AppError,
+    msgpack::{accepts_msgpack, to_msgpack, MSGPACK},
+};
+
+/// Serializes `value` as JSON, or MessagePack when the request accepts it,
+/// with an `ETag` hashed from the body, or answers `304 Not Modified` when
+/// the request's `If-None-Match` already names it.
+pub fn negotiated_with_etag<T: Serialize>(
+    headers: &HeaderMap,
+    value: &T,
+) -> Result<Response, AppError> {
+    let (body, content_type) = if accepts_msgpack(headers) {
+        (to_msgpack(value)?, MSGPACK)
+    } else {
+        let body = serde_json::to_vec(value)
+            .map_err(|e| AppError::Internal(format!("failed to serialize response: {}", e)))?;
+        (body, "application/json")
+    };
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+    let etag_header = (
+        header::ETAG,
+        HeaderValue::from_str(&etag).expect("hex etag is a valid header value"),
+    );
+
+    if if_none_match(headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [etag_header]).into_response());
+    }
+
+    Ok((
+        [
+            etag_header,
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_etag(headers: &HeaderMap) -> String {
+        let response = negotiated_with_etag(headers, &vec![1, 2, 3]).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn matching_etag_is_not_modified() {
+        let etag = get_etag(&HeaderMap::new());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+        let response = negotiated_with_etag(&headers, &vec![1, 2, 3]).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn changed_body_gets_a_new_etag() {
+        let etag = get_etag(&HeaderMap::new());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+        let response = negotiated_with_etag(&headers, &vec![1, 2, 4]).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag.as_str());
+    }
+
+    #[test]
+    fn if_none_match_accepts_lists_weak_tags_and_wildcards() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"other\", W/\"abc\""),
+        );
+        assert!(if_none_match(&headers, "\"abc\""));
+        assert!(!if_none_match(&headers, "\"abd\""));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(if_none_match(&headers, "\"anything\""));
+    }
+}
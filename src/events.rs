@@ -0,0 +1,131 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::{IntoParams, ToSchema};
+
+use level_server::{api::LevelObject, queries::sanitize_version};
+
+use crate::{environments::Environment, error::AppError};
+
+/// Events buffered per subscriber before a slow client starts missing some.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A change to one level version, pushed to every `/ws` client watching it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum LevelEvent {
+    Set {
+        version: String,
+        object: LevelObject,
+    },
+    Update {
+        version: String,
+        object: LevelObject,
+    },
+    Delete {
+        version: String,
+        id: i32,
+    },
+    /// The level changed as a whole, e.g. it was cleared, replaced,
+    /// renumbered or dropped. Clients should fetch it again.
+    Reload {
+        version: String,
+    },
+}
+
+impl LevelEvent {
+    fn version(&self) -> &str {
+        match self {
+            LevelEvent::Set { version, .. }
+            | LevelEvent::Update { version, .. }
+            | LevelEvent::Delete { version, .. }
+            | LevelEvent::Reload { version } => version,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Events(broadcast::Sender<LevelEvent>);
+
+impl Events {
+    pub fn new() -> Self {
+        Events(broadcast::channel(CHANNEL_CAPACITY).0)
+    }
+
+    /// Sends `event` to the connected clients. Called after the write has
+    /// been committed; having nobody listening is not an error.
+    pub fn publish(&self, event: LevelEvent) {
+        let _ = self.0.send(event);
+    }
+
+    /// Receives every event published from now on, of every version.
+    pub fn subscribe(&self) -> broadcast::Receiver<LevelEvent> {
+        self.0.subscribe()
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscribeParams {
+    version: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    params(SubscribeParams),
+    responses(
+        (status = 101, description = "Switched to a WebSocket carrying LevelEvent messages"),
+        (status = 400, description = "Invalid version", body = ErrorResponse),
+    ),
+    tag = "objects"
+)]
+pub async fn subscribe(
+    Environment { events, .. }: Environment,
+    Query(params): Query<SubscribeParams>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    let version = sanitize_version(&params.version)?;
+    let receiver = events.subscribe();
+    Ok(ws.on_upgrade(move |socket| forward_events(socket, receiver, version)))
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<LevelEvent>,
+    version: String,
+) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) if event.version() == version => {
+                    let text = match serde_json::to_string(&event) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            tracing::error!("failed to serialize level event: {}", e);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("websocket client for version {} missed {} events", version, skipped);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug)]
+pub struct ParseVectorError {
+    input: String,
+    expected: usize,
+}
+
+impl fmt::Display for ParseVectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {} comma separated numbers, got {:?}",
+            self.expected, self.input
+        )
+    }
+}
+
+impl std::error::Error for ParseVectorError {}
+
+/// Parses `"a,b,c"` (optionally wrapped in parentheses, as Unity prints
+/// vectors) into exactly `N` floats.
+fn parse_components<const N: usize>(input: &str) -> Result<[f64; N], ParseVectorError> {
+    let err = || ParseVectorError {
+        input: input.to_string(),
+        expected: N,
+    };
+
+    let trimmed = input.trim();
+    let trimmed = trimmed
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(trimmed);
+
+    let mut components = [0.0; N];
+    let mut parts = trimmed.split(',');
+    for component in components.iter_mut() {
+        let part = parts.next().ok_or_else(err)?;
+        *component = part
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(err)?;
+    }
+    if parts.next().is_some() {
+        return Err(err());
+    }
+    Ok(components)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl FromStr for Vec3 {
+    type Err = ParseVectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [x, y, z] = parse_components(s)?;
+        Ok(Vec3 { x, y, z })
+    }
+}
+
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.x, self.y, self.z)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Quat {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl FromStr for Quat {
+    type Err = ParseVectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [x, y, z, w] = parse_components(s)?;
+        Ok(Quat { x, y, z, w })
+    }
+}
+
+impl fmt::Display for Quat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.z, self.w)
+    }
+}
+
+/// Placement of an object, stored as ten numeric columns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    /// The components in column order: `pos_x, pos_y, pos_z, rot_x, rot_y,
+    /// rot_z, rot_w, scl_x, scl_y, scl_z`.
+    pub fn components(&self) -> [f64; 10] {
+        let Transform {
+            position,
+            rotation,
+            scale,
+        } = self;
+        [
+            position.x, position.y, position.z, rotation.x, rotation.y, rotation.z, rotation.w,
+            scale.x, scale.y, scale.z,
+        ]
+    }
+}
+
+/// The physics shape of an object, stored as JSONB and tagged by `type`, e.g.
+/// `{"type": "sphere", "radius": 0.5}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Collider {
+    Box {
+        size: Vec3,
+    },
+    Sphere {
+        radius: f64,
+    },
+    Capsule {
+        radius: f64,
+        height: f64,
+    },
+    Mesh {
+        mesh: String,
+        #[serde(default)]
+        convex: bool,
+    },
+}
+
+impl Collider {
+    /// Whether every dimension is positive, which Unity's physics needs.
+    pub fn has_positive_dimensions(&self) -> bool {
+        match self {
+            Collider::Box { size } => size.x > 0.0 && size.y > 0.0 && size.z > 0.0,
+            Collider::Sphere { radius } => *radius > 0.0,
+            Collider::Capsule { radius, height } => *radius > 0.0 && *height > 0.0,
+            Collider::Mesh { .. } => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_and_unity_formatted_vectors() {
+        let expected = Vec3 {
+            x: 1.0,
+            y: -2.5,
+            z: 3.0,
+        };
+        assert_eq!("1,-2.5,3".parse::<Vec3>().unwrap(), expected);
+        assert_eq!("(1, -2.5, 3)".parse::<Vec3>().unwrap(), expected);
+        assert_eq!(
+            " 0,0,0,1 ".parse::<Quat>().unwrap(),
+            Quat {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_component_counts() {
+        assert!("1,2".parse::<Vec3>().is_err());
+        assert!("1,2,3,4".parse::<Vec3>().is_err());
+        assert!("1,2,3".parse::<Quat>().is_err());
+        assert!("".parse::<Vec3>().is_err());
+    }
+
+    #[test]
+    fn rejects_non_finite_and_non_numeric_components() {
+        assert!("1,NaN,3".parse::<Vec3>().is_err());
+        assert!("inf,0,0".parse::<Vec3>().is_err());
+        assert!("1,two,3".parse::<Vec3>().is_err());
+        assert!("(1,2,3".parse::<Vec3>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let rotation = Quat {
+            x: 0.5,
+            y: -0.5,
+            z: 0.25,
+            w: 1.0,
+        };
+        assert_eq!(rotation.to_string().parse::<Quat>().unwrap(), rotation);
+    }
+
+    #[test]
+    fn collider_dimensions_must_be_positive() {
+        assert!(Collider::Sphere { radius: 0.5 }.has_positive_dimensions());
+        assert!(!Collider::Capsule {
+            radius: 0.5,
+            height: 0.0
+        }
+        .has_positive_dimensions());
+        let collider: Collider =
+            serde_json::from_str(r#"{"type": "box", "size": {"x": 1, "y": 1, "z": -1}}"#).unwrap();
+        assert!(!collider.has_positive_dimensions());
+    }
+}
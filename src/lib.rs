@@ -0,0 +1,7 @@
+//! Types shared by the level server and the Rust tools that talk to it.
+
+pub mod api;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod geometry;
+pub mod queries;
@@ -1,20 +1,273 @@
-use sqlx::migrate::Migrator;
+use level_server::geometry::{Collider, Quat, Transform, Vec3};
+use level_server::queries;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::types::Json as SqlJson;
 use sqlx::PgPool;
+use std::collections::HashMap;
 use std::env;
 
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
+const USAGE: &str =
+    "usage: migration_tool [run | revert [TARGET_VERSION] | status | seed [VERSION] [COUNT]]";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
     let database_url =
         env::var("DATABASE_URL").expect("DATABASE_URL environment variable must be set");
 
     let pool = PgPool::connect(&database_url).await?;
 
-    // Run migrations
-    MIGRATOR.run(&pool).await?;
+    match args.first().map(String::as_str) {
+        None | Some("run") => run(&pool).await?,
+        Some("revert") => {
+            let target = match args.get(1) {
+                Some(target) => Some(target.parse::<i64>().map_err(|_| {
+                    format!(
+                        "TARGET_VERSION must be a migration version, got {:?}",
+                        target
+                    )
+                })?),
+                None => None,
+            };
+            revert(&pool, target).await?
+        }
+        Some("status") => status(&pool).await?,
+        Some("seed") => {
+            let version = args.get(1).map(String::as_str).unwrap_or(SEED_VERSION);
+            let count = match args.get(2) {
+                Some(count) => count
+                    .parse::<usize>()
+                    .map_err(|_| format!("COUNT must be a number of objects, got {:?}", count))?,
+                None => SEED_COUNT,
+            };
+            seed(&pool, version, count).await?
+        }
+        Some(other) => {
+            eprintln!("unknown command {:?}\n{}", other, USAGE);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
+
+/// Versions of the applied migrations, oldest first.
+async fn applied_versions(pool: &PgPool) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let mut applied: Vec<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+    applied.sort_unstable();
+    Ok(applied)
+}
+
+async fn run(pool: &PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let before = applied_versions(pool).await?;
+
+    MIGRATOR.run(pool).await?;
 
-    println!("Migrations applied successfully.");
+    let after = applied_versions(pool).await?;
+    let newly_applied: Vec<i64> = after.into_iter().filter(|v| !before.contains(v)).collect();
+    if newly_applied.is_empty() {
+        println!("No pending migrations.");
+    } else {
+        for version in newly_applied {
+            println!("Applied {}", describe(version));
+        }
+        println!("Migrations applied successfully.");
+    }
 
     Ok(())
 }
+
+/// Reverts every applied migration newer than `target`. Without a target only
+/// the most recent migration is reverted. The first migration, a one-off fix
+/// of the original `objects_v0` table, has no down script and stays applied.
+async fn revert(pool: &PgPool, target: Option<i64>) -> Result<(), Box<dyn std::error::Error>> {
+    let before = applied_versions(pool).await?;
+    let Some(latest) = before.last().copied() else {
+        println!("No applied migrations to revert.");
+        return Ok(());
+    };
+    let target = target.unwrap_or_else(|| before.iter().rev().nth(1).copied().unwrap_or(0));
+    if target >= latest {
+        println!("Nothing to revert: latest applied migration is {}.", latest);
+        return Ok(());
+    }
+
+    MIGRATOR.undo(pool, target).await?;
+
+    let after = applied_versions(pool).await?;
+    let reverted: Vec<&i64> = before.iter().filter(|v| !after.contains(v)).collect();
+    if reverted.is_empty() {
+        println!(
+            "Nothing reverted: migrations newer than {} have no down script.",
+            target
+        );
+    } else {
+        for version in reverted.into_iter().rev() {
+            println!("Reverted {}", describe(*version));
+        }
+    }
+
+    Ok(())
+}
+
+async fn status(pool: &PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: HashMap<i64, _> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m))
+        .collect();
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+    {
+        let state = if applied.contains_key(&migration.version) {
+            "applied"
+        } else {
+            "pending"
+        };
+        println!(
+            "{:<8} {} {}",
+            state, migration.version, migration.description
+        );
+    }
+
+    Ok(())
+}
+
+fn describe(version: i64) -> String {
+    match MIGRATOR.iter().find(|m| m.version == version) {
+        Some(migration) => format!("{} {}", version, migration.description),
+        None => version.to_string(),
+    }
+}
+
+/// Level and number of objects `seed` fills when not given.
+const SEED_VERSION: &str = "1";
+const SEED_COUNT: usize = 12;
+
+/// Creates `version` if needed and adds `count` objects cycling through a
+/// few representative types, so a fresh local database has data to work with.
+async fn seed(
+    pool: &PgPool,
+    version: &str,
+    count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Ok(prefix) = env::var("TABLE_PREFIX") {
+        // Interpolated into SQL like the version; the server checks it more
+        // thoroughly at startup.
+        if !prefix
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(format!("invalid TABLE_PREFIX {:?}", prefix).into());
+        }
+        queries::set_table_prefix(prefix);
+    }
+    let version = queries::sanitize_version(version).map_err(|e| e.to_string())?;
+
+    let mut tx = pool.begin().await?;
+
+    let query_string = queries::create_table_sql(version.clone());
+    sqlx::query(query_string.as_str()).execute(&mut *tx).await?;
+    let query_string = queries::upgrade_table_sql(version.clone());
+    sqlx::query(query_string.as_str()).execute(&mut *tx).await?;
+
+    let query_string = queries::set_object_sql(version.clone());
+    for index in 0..count {
+        let (object_type, collider, tags) = sample_object(index);
+        // Laid out in rows of four, each turned a bit further around Y.
+        let angle = index as f64 * std::f64::consts::FRAC_PI_8;
+        let transform = Transform {
+            position: Vec3 {
+                x: (index % 4) as f64 * 2.0,
+                y: 0.0,
+                z: (index / 4) as f64 * 2.0,
+            },
+            rotation: Quat {
+                x: 0.0,
+                y: (angle / 2.0).sin(),
+                z: 0.0,
+                w: (angle / 2.0).cos(),
+            },
+            scale: Vec3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+        };
+
+        let mut insert = sqlx::query_scalar::<_, i32>(query_string.as_str()).bind(object_type);
+        for component in transform.components() {
+            insert = insert.bind(component);
+        }
+        let id = insert
+            .bind(collider.as_ref().map(SqlJson))
+            .bind(&tags)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        println!(
+            "Inserted {:>4} {:<10} at ({}) collider {} tags [{}]",
+            id,
+            object_type,
+            transform.position,
+            collider
+                .map(|c| serde_json::to_string(&c).expect("a collider always serializes"))
+                .unwrap_or_else(|| "none".to_string()),
+            tags.join(", ")
+        );
+    }
+
+    tx.commit().await?;
+    println!("Seeded {} objects into level {}.", count, version);
+
+    Ok(())
+}
+
+/// The `index`th sample object: its type, collider and tags.
+fn sample_object(index: usize) -> (&'static str, Option<Collider>, Vec<String>) {
+    let (object_type, collider, tags): (_, _, &[&str]) = match index % 6 {
+        0 => ("spawn", None, &["player"]),
+        1 => (
+            "prop",
+            Some(Collider::Box {
+                size: Vec3 {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                },
+            }),
+            &["crate"],
+        ),
+        2 => ("light", None, &["ambient"]),
+        3 => ("trigger", Some(Collider::Sphere { radius: 2.0 }), &["door"]),
+        4 => (
+            "collider",
+            Some(Collider::Capsule {
+                radius: 0.5,
+                height: 2.0,
+            }),
+            &[],
+        ),
+        _ => ("checkpoint", None, &["race"]),
+    };
+    (
+        object_type,
+        collider,
+        tags.iter().map(|tag| tag.to_string()).collect(),
+    )
+}
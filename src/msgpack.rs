@@ -0,0 +1,45 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+
+use crate::error::AppError;
+
+pub const MSGPACK: &str = "application/msgpack";
+
+/// Whether the request's `Accept` header asks for MessagePack.
+pub fn accepts_msgpack(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.split(',').any(|t| t.trim().starts_with(MSGPACK)))
+}
+
+/// Whether the request body is MessagePack rather than JSON.
+pub fn is_msgpack(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().starts_with(MSGPACK))
+}
+
+/// Encodes `value` with field names, so it reads like the JSON form and
+/// tagged enums such as `Collider` survive the round trip.
+pub fn to_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>, AppError> {
+    rmp_serde::to_vec_named(value)
+        .map_err(|e| AppError::Internal(format!("failed to serialize response: {}", e)))
+}
+
+/// Responds with MessagePack when the request accepts it, JSON otherwise.
+pub fn negotiate<T: Serialize>(headers: &HeaderMap, value: &T) -> Result<Response, AppError> {
+    if !accepts_msgpack(headers) {
+        return Ok(Json(value).into_response());
+    }
+    Ok((
+        [(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK))],
+        to_msgpack(value)?,
+    )
+        .into_response())
+}
@@ -0,0 +1,194 @@
+use utoipa::{
+    openapi::{
+        path::{ParameterBuilder, ParameterIn},
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+        ObjectBuilder, Required, SchemaType,
+    },
+    Modify, OpenApi,
+};
+
+use level_server::{
+    api::{
+        CountResponse, DeleteObjectResponse, ErrorResponse, FieldError, GetObjectsResponse,
+        LevelObject, ListVersionsResponse, NewLevelObject, PatchLevelObjectRequest,
+        SetLevelObjectRequest, SetLevelObjectsRequest, SetObjectsResponse,
+        UpdateLevelObjectRequest,
+    },
+    geometry::{Collider, Quat, Vec3},
+};
+
+use crate::{
+    events::LevelEvent, AppliedMigration, AuditEntry, AuditOperation, AuditResponse, BatchRequest,
+    BatchResponse, ChangedObject, CopyLevelRequest, CreateLevelResponse, DeleteLevelResponse,
+    DeleteObjectsRequest, DiffResponse, DuplicateObjectRequest, GetCountsRequest,
+    GetCountsResponse, GetIdResponse, GetObjectsByIdsRequest, HealthResponse, LevelDocument,
+    LevelMetadata, LevelSizeResponse, LevelUpdatedAtResponse, ListSnapshotsResponse,
+    LockObjectRequest, MigrationStatusResponse, MoveObjectRequest, ObjectLock,
+    ObjectsSinceResponse, Operation, OperationResult, PendingMigration, PoolStats,
+    PrepareDryRunResponse, RestoreObjectRequest, RestoreSnapshotRequest, ScaleObjectRequest,
+    SetLevelMetadataRequest, SnapshotInfo, TransformObjectsRequest, UndoRequest, UndoResponse,
+    UndoneChange,
+};
+
+/// The OpenAPI description served at `/api-docs/openapi.json` and browsable
+/// at `/swagger-ui`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "level-server", description = "Stores and serves level objects per version."),
+    paths(
+        crate::livez,
+        crate::health,
+        crate::get_object,
+        crate::get_first_id,
+        crate::get_last_id,
+        crate::get_objects,
+        crate::get_objects_by_ids,
+        crate::get_objects_in_box,
+        crate::search_objects,
+        crate::get_objects_since,
+        crate::export_level,
+        crate::count_objects,
+        crate::get_counts,
+        crate::level_updated_at,
+        crate::level_size,
+        crate::list_versions,
+        crate::migration_status,
+        crate::list_snapshots,
+        crate::diff_levels,
+        crate::events::subscribe,
+        crate::prepare_table,
+        crate::create_level,
+        crate::clear_level,
+        crate::delete_object,
+        crate::delete_objects,
+        crate::restore_object,
+        crate::duplicate_object,
+        crate::set_object,
+        crate::set_objects,
+        crate::update_object,
+        crate::patch_object,
+        crate::move_object,
+        crate::scale_object,
+        crate::touch_object,
+        crate::lock_object,
+        crate::unlock_object,
+        crate::transform_objects,
+        crate::batch,
+        crate::import_level,
+        crate::copy_level,
+        crate::delete_level,
+        crate::compact_level,
+        crate::get_level_metadata,
+        crate::set_level_metadata,
+        crate::undo,
+        crate::audit_log,
+        crate::create_snapshot,
+        crate::restore_snapshot,
+    ),
+    components(schemas(
+        Vec3,
+        Quat,
+        Collider,
+        LevelObject,
+        LevelEvent,
+        ErrorResponse,
+        FieldError,
+        HealthResponse,
+        PoolStats,
+        GetIdResponse,
+        GetObjectsResponse,
+        ObjectsSinceResponse,
+        GetObjectsByIdsRequest,
+        LevelDocument,
+        CountResponse,
+        GetCountsRequest,
+        GetCountsResponse,
+        ListVersionsResponse,
+        AppliedMigration,
+        PendingMigration,
+        MigrationStatusResponse,
+        SetLevelObjectRequest,
+        NewLevelObject,
+        SetLevelObjectsRequest,
+        UpdateLevelObjectRequest,
+        PatchLevelObjectRequest,
+        SetObjectsResponse,
+        MoveObjectRequest,
+        ScaleObjectRequest,
+        TransformObjectsRequest,
+        LockObjectRequest,
+        ObjectLock,
+        Operation,
+        BatchRequest,
+        OperationResult,
+        BatchResponse,
+        DeleteObjectResponse,
+        DeleteObjectsRequest,
+        RestoreObjectRequest,
+        DuplicateObjectRequest,
+        CopyLevelRequest,
+        DeleteLevelResponse,
+        LevelUpdatedAtResponse,
+        LevelSizeResponse,
+        LevelMetadata,
+        SetLevelMetadataRequest,
+        UndoRequest,
+        UndoneChange,
+        UndoResponse,
+        AuditOperation,
+        AuditEntry,
+        AuditResponse,
+        SnapshotInfo,
+        ListSnapshotsResponse,
+        RestoreSnapshotRequest,
+        PrepareDryRunResponse,
+        CreateLevelResponse,
+        ChangedObject,
+        DiffResponse,
+    )),
+    modifiers(&ApiKeyHeader, &EnvParameter),
+    tags(
+        (name = "objects", description = "Reading and editing level objects"),
+        (name = "levels", description = "Whole level versions"),
+        (name = "history", description = "Undo and snapshots"),
+        (name = "system", description = "Health and migrations"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `x-api-key` header that write endpoints require when
+/// `API_KEY` is set.
+struct ApiKeyHeader;
+
+impl Modify for ApiKeyHeader {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+        );
+    }
+}
+
+/// Adds the `env` query parameter, which every endpoint accepts to pick the
+/// database it works on.
+struct EnvParameter;
+
+impl Modify for EnvParameter {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for path in openapi.paths.paths.values_mut() {
+            for operation in path.operations.values_mut() {
+                let env = ParameterBuilder::new()
+                    .name("env")
+                    .parameter_in(ParameterIn::Query)
+                    .required(Required::False)
+                    .description(Some(
+                        "Environment to use, as named by a DATABASE_URL_{NAME} variable. Defaults to the primary database.",
+                    ))
+                    .schema(Some(ObjectBuilder::new().schema_type(SchemaType::String)))
+                    .build();
+                operation.parameters.get_or_insert_with(Vec::new).push(env);
+            }
+        }
+    }
+}
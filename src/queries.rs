@@ -1,40 +1,989 @@
+//! SQL for the level tables and the shared bookkeeping tables, used by both
+//! the server and `migration_tool` so seeded levels match the server's.
+
+use std::{fmt, sync::OnceLock};
+
+/// Longest version token accepted as part of a table name.
+const MAX_VERSION_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum VersionError {
+    Empty,
+    TooLong,
+    NotNumeric(char),
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionError::Empty => write!(f, "version must not be empty"),
+            VersionError::TooLong => {
+                write!(f, "version must be at most {} characters", MAX_VERSION_LEN)
+            }
+            VersionError::NotNumeric(c) => {
+                write!(f, "version must only contain digits, found {:?}", c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
+/// Start of every level table name, followed by the version.
+pub const DEFAULT_TABLE_PREFIX: &str = "objects_v";
+
+static TABLE_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Replaces `objects_v` as the start of every level table name, so several
+/// deployments can share a database. Must be called before any query is
+/// built, and only the first call has an effect. The prefix must already be
+/// validated, since it is interpolated like the version.
+pub fn set_table_prefix(prefix: String) {
+    let _ = TABLE_PREFIX.set(prefix);
+}
+
+pub fn table_prefix() -> &'static str {
+    TABLE_PREFIX.get_or_init(|| DEFAULT_TABLE_PREFIX.to_string())
+}
+
+/// Checks that `version` is a number, as the `objects_v{version}` table names
+/// intend, which also makes it safe to interpolate into SQL. Kept separate
+/// from the table naming so the rule can change once levels share a table.
+pub fn validate_numeric_version(version: &str) -> Result<(), VersionError> {
+    if version.is_empty() {
+        return Err(VersionError::Empty);
+    }
+    if version.len() > MAX_VERSION_LEN {
+        return Err(VersionError::TooLong);
+    }
+    if let Some(c) = version.chars().find(|c| !c.is_ascii_digit()) {
+        return Err(VersionError::NotNumeric(c));
+    }
+    Ok(())
+}
+
+/// Returns `version` for use in a table name once it passes
+/// `validate_numeric_version`.
+pub fn sanitize_version(version: &str) -> Result<String, VersionError> {
+    validate_numeric_version(version)?;
+    Ok(version.to_string())
+}
+
 pub fn get_object_by_id_sql(version: String) -> String {
-    format!("SELECT * FROM objects_v{} WHERE id = $1", version.as_str())
+    let prefix = table_prefix();
+    format!(
+        "SELECT * FROM {prefix}{} WHERE id = $1 AND deleted_at IS NULL",
+        version.as_str()
+    )
+}
+
+/// Selects the live objects whose ids are in `$1`, in the order the ids are
+/// given. Missing ids are skipped.
+pub fn get_objects_by_ids_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT o.* FROM UNNEST($1::integer[]) WITH ORDINALITY AS requested(id, position)
+        JOIN {prefix}{} AS o ON o.id = requested.id
+        WHERE o.deleted_at IS NULL
+        ORDER BY requested.position"#,
+        version.as_str()
+    )
 }
 
 pub fn get_objects_sql(version: String) -> String {
-    format!("SELECT * FROM objects_v{}", version.as_str())
+    let prefix = table_prefix();
+    format!(
+        "SELECT * FROM {prefix}{} WHERE deleted_at IS NULL ORDER BY id LIMIT $1 OFFSET $2",
+        version.as_str()
+    )
+}
+
+/// Like `get_objects_sql`, keeping only objects of type `$3`, with tag `$4`
+/// and an id above the cursor `$5`. A NULL filter matches everything.
+pub fn get_objects_filtered_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT * FROM {prefix}{} WHERE deleted_at IS NULL
+        AND ($3::text IS NULL OR object_type = $3)
+        AND ($4::text IS NULL OR $4 = ANY(tags))
+        AND ($5::integer IS NULL OR id > $5)
+        ORDER BY id LIMIT $1 OFFSET $2"#,
+        version.as_str()
+    )
+}
+
+/// Selects objects whose position lies inside the box `($1, $2, $3)` to
+/// `($4, $5, $6)`, inclusive.
+pub fn get_objects_in_box_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT * FROM {prefix}{}
+        WHERE pos_x BETWEEN $1 AND $4
+        AND pos_y BETWEEN $2 AND $5
+        AND pos_z BETWEEN $3 AND $6
+        AND deleted_at IS NULL
+        ORDER BY id"#,
+        version.as_str()
+    )
 }
 
 pub fn delete_all_sql(version: String) -> String {
-    format!("DELETE FROM objects_v{}", version.as_str())
+    let prefix = table_prefix();
+    format!("DELETE FROM {prefix}{}", version.as_str())
+}
+
+pub fn delete_object_by_id_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("DELETE FROM {prefix}{} WHERE id = $1", version.as_str())
+}
+
+pub fn delete_objects_by_ids_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "DELETE FROM {prefix}{} WHERE id = ANY($1) RETURNING id",
+        version.as_str()
+    )
+}
+
+/// Marks the object as deleted, keeping the row so it can be restored.
+pub fn soft_delete_object_by_id_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "UPDATE {prefix}{} SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL",
+        version.as_str()
+    )
+}
+
+pub fn soft_delete_objects_by_ids_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "UPDATE {prefix}{} SET deleted_at = now() WHERE id = ANY($1) AND deleted_at IS NULL RETURNING id",
+        version.as_str()
+    )
+}
+
+pub fn restore_object_by_id_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "UPDATE {prefix}{} SET deleted_at = NULL, updated_at = now() WHERE id = $1 AND deleted_at IS NOT NULL RETURNING *",
+        version.as_str()
+    )
 }
 
 pub fn get_first_id_sql(version: String) -> String {
-    format!("SELECT MIN(id) FROM objects_v{}", version.as_str())
+    let prefix = table_prefix();
+    format!(
+        "SELECT MIN(id) FROM {prefix}{} WHERE deleted_at IS NULL",
+        version.as_str()
+    )
+}
+
+pub fn get_last_id_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT MAX(id) FROM {prefix}{} WHERE deleted_at IS NULL",
+        version.as_str()
+    )
+}
+
+/// When the level last changed: the latest update or soft delete of any row,
+/// or a hard delete recorded in the history. NULL for a level never written.
+pub fn level_updated_at_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT GREATEST(
+            (SELECT MAX(GREATEST(updated_at, deleted_at)) FROM {prefix}{0}),
+            (SELECT MAX(changed_at) FROM {prefix}{0}_history)
+        )"#,
+        version.as_str()
+    )
+}
+
+/// Bytes the level table takes on disk, indexes and TOAST included, and how
+/// many rows it stores, soft deleted ones included.
+pub fn level_size_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT pg_total_relation_size('{prefix}{0}'::regclass) AS size_bytes, (SELECT COUNT(*) FROM {prefix}{0}) AS row_count",
+        version.as_str()
+    )
 }
 
 pub fn get_row_count_sql(version: String) -> String {
+    let prefix = table_prefix();
     format!(
-        "SELECT COUNT(*) AS row_count FROM objects_v{}",
+        "SELECT COUNT(*) AS row_count FROM {prefix}{} WHERE deleted_at IS NULL",
+        version.as_str()
+    )
+}
+
+/// Every row of the level, soft deleted ones included.
+pub fn get_stored_row_count_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT COUNT(*) AS row_count FROM {prefix}{}",
+        version.as_str()
+    )
+}
+
+/// Which of the versions in `$1` have a level table.
+pub fn existing_versions_sql() -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT version FROM unnest($1::text[]) AS version WHERE to_regclass('{prefix}' || version) IS NOT NULL"
+    )
+}
+
+/// Counts the live objects of every level in `versions` in one query. Each
+/// of them must have a table.
+pub fn count_levels_sql(versions: &[String]) -> String {
+    let prefix = table_prefix();
+    versions
+        .iter()
+        .map(|version| {
+            format!(
+                "SELECT '{0}'::text AS version, COUNT(*) AS row_count FROM {prefix}{0} WHERE deleted_at IS NULL",
+                version.as_str()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ")
+}
+
+/// Counts the objects `get_objects_filtered_sql` pages through, with the
+/// type in `$1` and the tag in `$2`.
+pub fn get_row_count_filtered_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT COUNT(*) AS row_count FROM {prefix}{} WHERE deleted_at IS NULL
+        AND ($1::text IS NULL OR object_type = $1)
+        AND ($2::text IS NULL OR $2 = ANY(tags))"#,
         version.as_str()
     )
 }
 
 pub fn set_object_sql(version: String) -> String {
-    format!("INSERT INTO objects_v{} (object_type, position, rotation, scale, collider) VALUES ($1, $2, $3, $4, $5)", version.as_str())
+    let prefix = table_prefix();
+    format!("INSERT INTO {prefix}{} (object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING *", version.as_str())
+}
+
+/// Like `set_object_sql` but with the id in `$14`, overwriting the row that
+/// already has that id.
+pub fn set_object_upsert_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("INSERT INTO {prefix}{0} (object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags, id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) ON CONFLICT (id) DO UPDATE SET object_type = EXCLUDED.object_type, pos_x = EXCLUDED.pos_x, pos_y = EXCLUDED.pos_y, pos_z = EXCLUDED.pos_z, rot_x = EXCLUDED.rot_x, rot_y = EXCLUDED.rot_y, rot_z = EXCLUDED.rot_z, rot_w = EXCLUDED.rot_w, scl_x = EXCLUDED.scl_x, scl_y = EXCLUDED.scl_y, scl_z = EXCLUDED.scl_z, collider = EXCLUDED.collider, tags = EXCLUDED.tags, rev = {prefix}{0}.rev + 1, updated_at = now(), deleted_at = NULL RETURNING *", version.as_str())
+}
+
+/// Moves the id sequence past the highest id, so rows inserted with an
+/// explicit id don't collide with later auto-increment inserts.
+pub fn sync_id_sequence_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT setval(pg_get_serial_sequence('{prefix}{0}', 'id'), GREATEST((SELECT MAX(id) FROM {prefix}{0}), 1))",
+        version.as_str()
+    )
+}
+
+/// Inserts one row per element of the bound arrays, so a whole batch goes
+/// through a single statement regardless of its size. Postgres arrays can't
+/// be ragged, so each row's tags arrive in `$13` as a JSON array string.
+pub fn set_objects_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("INSERT INTO {prefix}{} (object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags) SELECT object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, ARRAY(SELECT jsonb_array_elements_text(tags::jsonb)) FROM UNNEST($1::text[], $2::float8[], $3::float8[], $4::float8[], $5::float8[], $6::float8[], $7::float8[], $8::float8[], $9::float8[], $10::float8[], $11::float8[], $12::jsonb[], $13::text[]) AS batch(object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags) RETURNING *", version.as_str())
+}
+
+/// Copies a live object into a new row with its own id.
+pub fn duplicate_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("INSERT INTO {prefix}{0} (object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags) SELECT object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags FROM {prefix}{0} WHERE id = $1 AND deleted_at IS NULL RETURNING *", version.as_str())
+}
+
+/// Copies every live object of version `{0}` into version `{1}`, giving the
+/// copies new ids.
+pub fn copy_level_sql(from_version: String, to_version: String) -> String {
+    let prefix = table_prefix();
+    format!("INSERT INTO {prefix}{1} (object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags) SELECT object_type, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, scl_x, scl_y, scl_z, collider, tags FROM {prefix}{0} WHERE deleted_at IS NULL ORDER BY id", from_version.as_str(), to_version.as_str())
+}
+
+/// Replaces a live object and bumps its `rev`. When `$15` is given the row
+/// is only updated if its `rev` still equals it.
+pub fn update_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("UPDATE {prefix}{} SET object_type = $2, pos_x = $3, pos_y = $4, pos_z = $5, rot_x = $6, rot_y = $7, rot_z = $8, rot_w = $9, scl_x = $10, scl_y = $11, scl_z = $12, collider = $13, tags = COALESCE($14, tags), rev = rev + 1, updated_at = now() WHERE id = $1 AND deleted_at IS NULL AND ($15::integer IS NULL OR rev = $15) RETURNING *", version.as_str())
+}
+
+/// Sets the given `columns` of a live object to `$3` onwards, in order. The
+/// update only applies if `$2` is NULL or equal to the object's rev.
+/// `columns` must be column names, never request input.
+pub fn patch_object_sql(version: String, columns: &[&str]) -> String {
+    let prefix = table_prefix();
+    let assignments: String = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("{} = ${}, ", column, i + 3))
+        .collect();
+    format!("UPDATE {prefix}{} SET {}rev = rev + 1, updated_at = now() WHERE id = $1 AND deleted_at IS NULL AND ($2::integer IS NULL OR rev = $2) RETURNING *", version.as_str(), assignments)
+}
+
+/// Sets the position (`$2..$4`) and rotation (`$5..$8`) of a live object,
+/// leaving its other columns as they are.
+pub fn move_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("UPDATE {prefix}{} SET pos_x = $2, pos_y = $3, pos_z = $4, rot_x = $5, rot_y = $6, rot_z = $7, rot_w = $8, rev = rev + 1, updated_at = now() WHERE id = $1 AND deleted_at IS NULL RETURNING *", version.as_str())
+}
+
+/// Sets the scale (`$2..$4`) of a live object.
+pub fn scale_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!("UPDATE {prefix}{} SET scl_x = $2, scl_y = $3, scl_z = $4, rev = rev + 1, updated_at = now() WHERE id = $1 AND deleted_at IS NULL RETURNING *", version.as_str())
+}
+
+/// Sets `updated_at` of a live object to now, leaving its data and rev as
+/// they are.
+pub fn touch_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "UPDATE {prefix}{} SET updated_at = now() WHERE id = $1 AND deleted_at IS NULL RETURNING *",
+        version.as_str()
+    )
+}
+
+/// The editor holding the lock on live object `$1` and when the lock ends,
+/// both NULL when it isn't locked or the lock is older than `$2` seconds.
+/// Locks the row so the lock can't change before the transaction ends.
+pub fn get_object_lock_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT id,
+            CASE WHEN locked_at > now() - make_interval(secs => $2) THEN locked_by END AS locked_by,
+            CASE WHEN locked_at > now() - make_interval(secs => $2) THEN locked_at + make_interval(secs => $2) END AS locked_until
+        FROM {prefix}{} WHERE id = $1 AND deleted_at IS NULL FOR UPDATE"#,
+        version.as_str()
+    )
+}
+
+/// Like [`get_object_lock_sql`], but for every object in `$1`, live or soft
+/// deleted. Rows are locked in id order so concurrent writers can't deadlock.
+pub fn get_object_locks_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT id,
+            CASE WHEN locked_at > now() - make_interval(secs => $2) THEN locked_by END AS locked_by,
+            CASE WHEN locked_at > now() - make_interval(secs => $2) THEN locked_at + make_interval(secs => $2) END AS locked_until
+        FROM {prefix}{} WHERE id = ANY($1) ORDER BY id FOR UPDATE"#,
+        version.as_str()
+    )
+}
+
+/// Locks live object `$1` for editor `$2`, unless another editor holds a lock
+/// younger than `$3` seconds. Locking again renews the lock.
+pub fn lock_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"UPDATE {prefix}{} SET locked_by = $2, locked_at = now()
+        WHERE id = $1 AND deleted_at IS NULL
+            AND (locked_by IS NULL OR locked_by = $2 OR locked_at <= now() - make_interval(secs => $3))
+        RETURNING id, locked_by, locked_at + make_interval(secs => $3) AS locked_until"#,
+        version.as_str()
+    )
+}
+
+/// Clears the lock on live object `$1`, unless another editor than `$2`
+/// holds a lock younger than `$3` seconds.
+pub fn unlock_object_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"UPDATE {prefix}{} SET locked_by = NULL, locked_at = NULL
+        WHERE id = $1 AND deleted_at IS NULL
+            AND (locked_by IS NULL OR locked_by = $2 OR locked_at <= now() - make_interval(secs => $3))
+        RETURNING id, locked_by, NULL::timestamptz AS locked_until"#,
+        version.as_str()
+    )
+}
+
+/// Live objects changed after `$1`, oldest change first.
+pub fn get_objects_since_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT * FROM {prefix}{} WHERE deleted_at IS NULL AND updated_at > $1 ORDER BY updated_at, id",
+        version.as_str()
+    )
+}
+
+/// Ids of the objects deleted after `$1`: soft deleted rows, and the
+/// deletes recorded in the history for rows that aren't live again.
+pub fn deleted_ids_since_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT id FROM {prefix}{0} WHERE deleted_at > $1
+        UNION
+        SELECT h.object_id FROM {prefix}{0}_history AS h
+        WHERE h.operation = 'delete' AND h.changed_at > $1
+            AND NOT EXISTS (SELECT 1 FROM {prefix}{0} AS o WHERE o.id = h.object_id AND o.deleted_at IS NULL)
+        ORDER BY id"#,
+        version.as_str()
+    )
+}
+
+/// Live objects whose type or one of whose tags matches the `ILIKE` pattern
+/// `$1`, at most `$2` of them.
+pub fn search_objects_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT * FROM {prefix}{} WHERE deleted_at IS NULL
+        AND (object_type ILIKE $1 OR EXISTS (SELECT 1 FROM unnest(tags) AS tag WHERE tag ILIKE $1))
+        ORDER BY id LIMIT $2"#,
+        version.as_str()
+    )
 }
 
 pub fn create_table_sql(version: String) -> String {
+    let prefix = table_prefix();
     format!(
-        r#"CREATE TABLE IF NOT EXISTS objects_v{} (
+        r#"CREATE TABLE IF NOT EXISTS {prefix}{} (
         id SERIAL PRIMARY KEY,
         object_type VARCHAR(255) NOT NULL,
-        position VARCHAR(255) NOT NULL,
-        scale VARCHAR(255) NOT NULL,
-        rotation VARCHAR(255) NOT NULL,
-        collider TEXT NOT NULL
+        pos_x DOUBLE PRECISION NOT NULL DEFAULT 0,
+        pos_y DOUBLE PRECISION NOT NULL DEFAULT 0,
+        pos_z DOUBLE PRECISION NOT NULL DEFAULT 0,
+        rot_x DOUBLE PRECISION NOT NULL DEFAULT 0,
+        rot_y DOUBLE PRECISION NOT NULL DEFAULT 0,
+        rot_z DOUBLE PRECISION NOT NULL DEFAULT 0,
+        rot_w DOUBLE PRECISION NOT NULL DEFAULT 1,
+        scl_x DOUBLE PRECISION NOT NULL DEFAULT 1,
+        scl_y DOUBLE PRECISION NOT NULL DEFAULT 1,
+        scl_z DOUBLE PRECISION NOT NULL DEFAULT 1,
+        collider JSONB,
+        tags TEXT[] NOT NULL DEFAULT '{{}}',
+        rev INTEGER NOT NULL DEFAULT 1,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        deleted_at TIMESTAMPTZ,
+        locked_by TEXT,
+        locked_at TIMESTAMPTZ
         )"#,
         version.as_str()
     )
 }
+
+/// Regex for one number inside a legacy `"x,y,z"` transform string.
+const LEGACY_NUMBER: &str = r"\s*[-+]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][-+]?[0-9]+)?\s*";
+
+/// Brings a table created by an older `create_table_sql` up to date:
+/// adds the timestamp, `deleted_at`, `tags`, `rev` and lock columns, the history
+/// table and trigger (with the `actor` column) and the snapshots table, converts a text
+/// `collider` to JSONB (keeping the text in `legacy_collider`, with NULL
+/// where it wasn't a JSON object), and splits the text `position`, `rotation` and
+/// `scale` columns into numeric ones. Rows whose text doesn't parse keep the
+/// column defaults; the original text of every row is kept in
+/// `legacy_position`, `legacy_rotation` and `legacy_scale`. Concurrent
+/// upgrades of the same table take turns instead of adding columns twice.
+pub fn upgrade_table_sql(version: String) -> String {
+    let prefix = table_prefix();
+    let vec3 = format!("^{0},{0},{0}$", LEGACY_NUMBER);
+    let quat = format!("^{0},{0},{0},{0}$", LEGACY_NUMBER);
+    format!(
+        r#"DO $$
+        BEGIN
+            PERFORM pg_advisory_xact_lock(hashtext('{prefix}{0}'));
+
+            IF NOT EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'updated_at' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0}
+                    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+            END IF;
+
+            IF NOT EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'deleted_at' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0} ADD COLUMN deleted_at TIMESTAMPTZ;
+            END IF;
+
+            IF NOT EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'tags' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0} ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{{}}';
+            END IF;
+
+            IF NOT EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'rev' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0} ADD COLUMN rev INTEGER NOT NULL DEFAULT 1;
+            END IF;
+
+            IF NOT EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'locked_by' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0}
+                    ADD COLUMN locked_by TEXT,
+                    ADD COLUMN locked_at TIMESTAMPTZ;
+            END IF;
+
+            IF to_regclass('idx_{prefix}{0}_type') IS NULL THEN
+                CREATE INDEX idx_{prefix}{0}_type ON {prefix}{0} (object_type);
+            END IF;
+
+            IF to_regclass('{prefix}{0}_history') IS NULL THEN
+                CREATE TABLE {prefix}{0}_history (
+                    history_id BIGSERIAL PRIMARY KEY,
+                    xact_id BIGINT NOT NULL DEFAULT txid_current(),
+                    object_id INTEGER NOT NULL,
+                    operation TEXT NOT NULL,
+                    snapshot JSONB,
+                    changed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                    actor TEXT
+                );
+            END IF;
+
+            IF NOT EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}_history'::regclass AND attname = 'actor' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0}_history ADD COLUMN actor TEXT;
+            END IF;
+
+            IF to_regclass('{prefix}{0}_snapshots') IS NULL THEN
+                CREATE TABLE {prefix}{0}_snapshots (
+                    snapshot_id BIGSERIAL PRIMARY KEY,
+                    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                    object_count INTEGER NOT NULL,
+                    objects JSONB NOT NULL
+                );
+            END IF;
+
+            -- The function is shared by every level, and older releases made
+            -- it without the actor, so every history table gets the column
+            -- before the function is replaced.
+            IF NOT EXISTS (SELECT 1 FROM pg_proc WHERE proname = 'record_object_history' AND prosrc LIKE '%level_server.actor%') THEN
+                PERFORM pg_advisory_xact_lock(hashtext('record_object_history'));
+                DECLARE
+                    history RECORD;
+                BEGIN
+                    FOR history IN
+                        SELECT c.oid::regclass AS name FROM pg_class c
+                        WHERE c.relkind = 'r' AND c.relname LIKE '%\_history' AND pg_table_is_visible(c.oid)
+                            AND EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = c.oid AND attname = 'xact_id' AND NOT attisdropped)
+                    LOOP
+                        EXECUTE format('ALTER TABLE %s ADD COLUMN IF NOT EXISTS actor TEXT', history.name);
+                    END LOOP;
+                END;
+
+                CREATE OR REPLACE FUNCTION record_object_history() RETURNS trigger LANGUAGE plpgsql AS $fn$
+                DECLARE
+                    actor TEXT := nullif(current_setting('level_server.actor', true), '');
+                    operation TEXT := lower(TG_OP);
+                BEGIN
+                    IF current_setting('level_server.undoing', true) = 'on' THEN
+                        RETURN NULL;
+                    END IF;
+                    IF TG_OP = 'INSERT' THEN
+                        EXECUTE format('INSERT INTO %I (object_id, operation, actor) VALUES ($1, $2, $3)', TG_TABLE_NAME || '_history')
+                            USING NEW.id, operation, actor;
+                        RETURN NULL;
+                    END IF;
+                    -- A soft delete is recorded as a delete.
+                    IF TG_OP = 'UPDATE' THEN
+                        IF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+                            operation := 'delete';
+                        END IF;
+                    END IF;
+                    EXECUTE format('INSERT INTO %I (object_id, operation, snapshot, actor) VALUES ($1, $2, $3, $4)', TG_TABLE_NAME || '_history')
+                        USING OLD.id, operation, to_jsonb(OLD), actor;
+                    RETURN NULL;
+                END
+                $fn$;
+            END IF;
+
+            IF NOT EXISTS (SELECT 1 FROM pg_trigger WHERE tgrelid = '{prefix}{0}'::regclass AND tgname = 'record_history') THEN
+                CREATE TRIGGER record_history AFTER INSERT OR UPDATE OR DELETE ON {prefix}{0}
+                    FOR EACH ROW EXECUTE FUNCTION record_object_history();
+            END IF;
+
+            IF (SELECT atttypid FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'collider' AND NOT attisdropped) = 'text'::regtype THEN
+                ALTER TABLE {prefix}{0} RENAME COLUMN collider TO legacy_collider;
+                ALTER TABLE {prefix}{0}
+                    ALTER COLUMN legacy_collider DROP NOT NULL,
+                    ADD COLUMN collider JSONB;
+                DECLARE
+                    legacy RECORD;
+                BEGIN
+                    FOR legacy IN SELECT id, legacy_collider FROM {prefix}{0} LOOP
+                        BEGIN
+                            UPDATE {prefix}{0} SET collider = legacy.legacy_collider::jsonb WHERE id = legacy.id;
+                        EXCEPTION WHEN invalid_text_representation THEN
+                            NULL;
+                        END;
+                    END LOOP;
+                END;
+                UPDATE {prefix}{0} SET collider = NULL WHERE jsonb_typeof(collider) <> 'object';
+            END IF;
+
+            IF EXISTS (SELECT 1 FROM pg_attribute WHERE attrelid = '{prefix}{0}'::regclass AND attname = 'position' AND NOT attisdropped) THEN
+                ALTER TABLE {prefix}{0} RENAME COLUMN position TO legacy_position;
+                ALTER TABLE {prefix}{0} RENAME COLUMN rotation TO legacy_rotation;
+                ALTER TABLE {prefix}{0} RENAME COLUMN scale TO legacy_scale;
+                ALTER TABLE {prefix}{0}
+                    ALTER COLUMN legacy_position DROP NOT NULL,
+                    ALTER COLUMN legacy_rotation DROP NOT NULL,
+                    ALTER COLUMN legacy_scale DROP NOT NULL,
+                    ADD COLUMN pos_x DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    ADD COLUMN pos_y DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    ADD COLUMN pos_z DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    ADD COLUMN rot_x DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    ADD COLUMN rot_y DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    ADD COLUMN rot_z DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    ADD COLUMN rot_w DOUBLE PRECISION NOT NULL DEFAULT 1,
+                    ADD COLUMN scl_x DOUBLE PRECISION NOT NULL DEFAULT 1,
+                    ADD COLUMN scl_y DOUBLE PRECISION NOT NULL DEFAULT 1,
+                    ADD COLUMN scl_z DOUBLE PRECISION NOT NULL DEFAULT 1;
+
+                UPDATE {prefix}{0} SET
+                    pos_x = split_part(btrim(legacy_position, '() '), ',', 1)::float8,
+                    pos_y = split_part(btrim(legacy_position, '() '), ',', 2)::float8,
+                    pos_z = split_part(btrim(legacy_position, '() '), ',', 3)::float8
+                WHERE btrim(legacy_position, '() ') ~ '{1}';
+
+                UPDATE {prefix}{0} SET
+                    rot_x = split_part(btrim(legacy_rotation, '() '), ',', 1)::float8,
+                    rot_y = split_part(btrim(legacy_rotation, '() '), ',', 2)::float8,
+                    rot_z = split_part(btrim(legacy_rotation, '() '), ',', 3)::float8,
+                    rot_w = split_part(btrim(legacy_rotation, '() '), ',', 4)::float8
+                WHERE btrim(legacy_rotation, '() ') ~ '{2}';
+
+                UPDATE {prefix}{0} SET
+                    scl_x = split_part(btrim(legacy_scale, '() '), ',', 1)::float8,
+                    scl_y = split_part(btrim(legacy_scale, '() '), ',', 2)::float8,
+                    scl_z = split_part(btrim(legacy_scale, '() '), ',', 3)::float8
+                WHERE btrim(legacy_scale, '() ') ~ '{1}';
+            END IF;
+        END
+        $$"#,
+        version.as_str(),
+        vec3,
+        quat
+    )
+}
+
+/// Lists every level table in the current schema, leaving out the
+/// `_history` and `_snapshots` tables kept alongside them.
+pub fn list_version_tables_sql() -> String {
+    let prefix = table_prefix().replace('_', "\\_");
+    format!(
+        r#"SELECT table_name::text FROM information_schema.tables
+        WHERE table_schema = current_schema() AND table_name LIKE '{prefix}%'
+        AND table_name NOT LIKE '%\_history' AND table_name NOT LIKE '%\_snapshots'
+        ORDER BY table_name"#
+    )
+}
+
+/// Stores every row of the level, soft-deleted ones included, as one
+/// snapshot.
+pub fn create_snapshot_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"INSERT INTO {prefix}{0}_snapshots (object_count, objects)
+        SELECT COUNT(*), COALESCE(jsonb_agg(to_jsonb(o) ORDER BY o.id), '[]') FROM {prefix}{0} AS o
+        RETURNING snapshot_id, created_at, object_count"#,
+        version.as_str()
+    )
+}
+
+pub fn list_snapshots_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT snapshot_id, created_at, object_count FROM {prefix}{}_snapshots ORDER BY snapshot_id",
+        version.as_str()
+    )
+}
+
+/// History entries of the level, newest first, `$1` per page from offset
+/// `$2`, only those whose operation is `$3` when it isn't NULL.
+pub fn audit_log_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT history_id, xact_id, object_id, operation, changed_at, actor FROM {prefix}{}_history
+        WHERE ($3::text IS NULL OR operation = $3)
+        ORDER BY history_id DESC LIMIT $1 OFFSET $2"#,
+        version.as_str()
+    )
+}
+
+/// Inserts the rows saved in snapshot `$1` with their original ids. Returns
+/// no rows if the snapshot doesn't exist.
+pub fn restore_snapshot_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"INSERT INTO {prefix}{0}
+        SELECT restored.* FROM {prefix}{0}_snapshots AS s,
+            jsonb_populate_recordset(NULL::{prefix}{0}, s.objects) AS restored
+        WHERE s.snapshot_id = $1"#,
+        version.as_str()
+    )
+}
+
+pub fn snapshot_exists_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT EXISTS (SELECT 1 FROM {prefix}{}_snapshots WHERE snapshot_id = $1)",
+        version.as_str()
+    )
+}
+
+/// Stops the history trigger recording changes for the rest of the
+/// transaction, so undoing a change doesn't record a new one.
+pub fn skip_history_sql() -> String {
+    "SELECT set_config('level_server.undoing', 'on', true)".to_string()
+}
+
+/// Records `$1` in the history as who made the rest of the transaction's
+/// changes.
+pub fn set_actor_sql() -> String {
+    "SELECT set_config('level_server.actor', $1, true)".to_string()
+}
+
+/// The history entries written by the most recent transaction that changed
+/// the level, newest first.
+pub fn last_change_history_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT history_id, object_id, operation FROM {prefix}{0}_history
+        WHERE xact_id = (SELECT xact_id FROM {prefix}{0}_history ORDER BY history_id DESC LIMIT 1)
+        ORDER BY history_id DESC
+        FOR UPDATE"#,
+        version.as_str()
+    )
+}
+
+/// Puts back the row recorded before the update in history entry `$1`. The
+/// `rev` still moves forward.
+pub fn undo_update_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"UPDATE {prefix}{0} AS t SET
+            object_type = s.object_type,
+            pos_x = s.pos_x, pos_y = s.pos_y, pos_z = s.pos_z,
+            rot_x = s.rot_x, rot_y = s.rot_y, rot_z = s.rot_z, rot_w = s.rot_w,
+            scl_x = s.scl_x, scl_y = s.scl_y, scl_z = s.scl_z,
+            collider = s.collider, tags = s.tags, deleted_at = s.deleted_at,
+            rev = t.rev + 1, updated_at = now()
+        FROM {prefix}{0}_history AS h, jsonb_populate_record(NULL::{prefix}{0}, h.snapshot) AS s
+        WHERE h.history_id = $1 AND t.id = h.object_id
+        RETURNING t.*"#,
+        version.as_str()
+    )
+}
+
+/// Re-inserts the row removed by the delete in history entry `$1`, keeping
+/// its id.
+pub fn undo_delete_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"INSERT INTO {prefix}{0}
+        SELECT (jsonb_populate_record(NULL::{prefix}{0}, snapshot)).* FROM {prefix}{0}_history
+        WHERE history_id = $1
+        RETURNING *"#,
+        version.as_str()
+    )
+}
+
+pub fn delete_history_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "DELETE FROM {prefix}{}_history WHERE history_id = ANY($1)",
+        version.as_str()
+    )
+}
+
+pub fn list_applied_migrations_sql() -> String {
+    "SELECT version, description, installed_on FROM _sqlx_migrations WHERE success ORDER BY version"
+        .to_string()
+}
+
+/// Metadata of version `$1`. Every deployment sharing the database uses the
+/// same `level_metadata` and `idempotency` tables, so their rows are keyed by
+/// the table prefix too.
+pub fn get_level_metadata_sql() -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT version, name, author, description, updated_at FROM level_metadata WHERE table_prefix = '{prefix}' AND version = $1"
+    )
+}
+
+/// Creates or updates the metadata of version `$1`. Fields given as NULL
+/// keep their current value, or start out empty.
+pub fn set_level_metadata_sql() -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"INSERT INTO level_metadata (table_prefix, version, name, author, description)
+    VALUES ('{prefix}', $1, COALESCE($2, ''), COALESCE($3, ''), COALESCE($4, ''))
+    ON CONFLICT (table_prefix, version) DO UPDATE SET
+        name = COALESCE($2, level_metadata.name),
+        author = COALESCE($3, level_metadata.author),
+        description = COALESCE($4, level_metadata.description),
+        updated_at = now()
+    RETURNING version, name, author, description, updated_at"#
+    )
+}
+
+pub fn level_exists_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "SELECT to_regclass('{prefix}{}') IS NOT NULL",
+        version.as_str()
+    )
+}
+
+/// Removes the metadata of a dropped level, if the `level_metadata` migration
+/// has been run.
+pub fn delete_level_metadata_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"DO $$ BEGIN
+        IF to_regclass('level_metadata') IS NOT NULL THEN
+            DELETE FROM level_metadata WHERE table_prefix = '{prefix}' AND version = '{}';
+        END IF;
+    END $$"#,
+        version.as_str()
+    )
+}
+
+/// Forgets idempotency keys older than `$1` seconds.
+pub fn expire_idempotency_keys_sql() -> String {
+    let prefix = table_prefix();
+    format!(
+        "DELETE FROM idempotency WHERE table_prefix = '{prefix}' AND created_at < now() - make_interval(secs => $1)"
+    )
+}
+
+/// Reserves key `$1`, affecting no row when it was already used. A request
+/// still holding the key makes this wait until it commits or rolls back.
+pub fn claim_idempotency_key_sql() -> String {
+    let prefix = table_prefix();
+    format!(
+        "INSERT INTO idempotency (table_prefix, key) VALUES ('{prefix}', $1) ON CONFLICT (table_prefix, key) DO NOTHING"
+    )
+}
+
+pub fn get_idempotent_response_sql() -> String {
+    let prefix = table_prefix();
+    format!("SELECT response FROM idempotency WHERE table_prefix = '{prefix}' AND key = $1")
+}
+
+/// Records the object `$2` and the response `$3` produced for key `$1`.
+pub fn save_idempotent_response_sql() -> String {
+    let prefix = table_prefix();
+    format!(
+        "UPDATE idempotency SET object_id = $2, response = $3 WHERE table_prefix = '{prefix}' AND key = $1"
+    )
+}
+
+/// Renumbers the level's rows 1, 2, ... in id order, live rows first, and
+/// restarts the id sequence after them. The history refers to the old ids,
+/// so it is emptied. Other writers wait for the transaction to end.
+pub fn compact_level_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"DO $$
+        BEGIN
+            LOCK TABLE {prefix}{0} IN EXCLUSIVE MODE;
+            -- Negate first so the new ids never collide with old ones.
+            UPDATE {prefix}{0} SET id = -id;
+            UPDATE {prefix}{0} AS t SET id = r.new_id
+            FROM (
+                SELECT id, row_number() OVER (ORDER BY deleted_at IS NOT NULL, id DESC) AS new_id
+                FROM {prefix}{0}
+            ) AS r
+            WHERE t.id = r.id;
+            DELETE FROM {prefix}{0}_history;
+            PERFORM setval(pg_get_serial_sequence('{prefix}{0}', 'id'), COALESCE((SELECT MAX(id) FROM {prefix}{0}), 0) + 1, false);
+        END
+        $$"#,
+        version.as_str()
+    )
+}
+
+/// Drops the level along with its history and snapshots.
+pub fn drop_level_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        "DROP TABLE IF EXISTS {prefix}{0}, {prefix}{0}_history, {prefix}{0}_snapshots",
+        version.as_str()
+    )
+}
+
+/// Live objects of version `{1}` whose id has no live object in version `{0}`.
+pub fn diff_added_sql(from_version: String, to_version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT * FROM {prefix}{1} AS t WHERE t.deleted_at IS NULL
+        AND NOT EXISTS (SELECT 1 FROM {prefix}{0} AS f WHERE f.id = t.id AND f.deleted_at IS NULL)
+        ORDER BY t.id"#,
+        from_version.as_str(),
+        to_version.as_str()
+    )
+}
+
+/// Ids live in both versions whose type, transform, collider or tags differ.
+pub fn diff_changed_ids_sql(from_version: String, to_version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"SELECT t.id FROM {prefix}{1} AS t JOIN {prefix}{0} AS f ON f.id = t.id
+        WHERE t.deleted_at IS NULL AND f.deleted_at IS NULL
+        AND (t.object_type, t.pos_x, t.pos_y, t.pos_z, t.rot_x, t.rot_y, t.rot_z, t.rot_w,
+            t.scl_x, t.scl_y, t.scl_z, t.collider, t.tags)
+        IS DISTINCT FROM (f.object_type, f.pos_x, f.pos_y, f.pos_z, f.rot_x, f.rot_y, f.rot_z, f.rot_w,
+            f.scl_x, f.scl_y, f.scl_z, f.collider, f.tags)
+        ORDER BY t.id"#,
+        from_version.as_str(),
+        to_version.as_str()
+    )
+}
+
+/// Offsets the position of the live objects with ids in `$1` by `$2..$4`,
+/// rotates them by the quaternion `$5..$8` (applied after their own
+/// rotation) and multiplies their scale by `$9..$11`.
+pub fn transform_objects_sql(version: String) -> String {
+    let prefix = table_prefix();
+    format!(
+        r#"UPDATE {prefix}{} SET
+            pos_x = pos_x + $2, pos_y = pos_y + $3, pos_z = pos_z + $4,
+            rot_x = $8 * rot_x + $5 * rot_w + $6 * rot_z - $7 * rot_y,
+            rot_y = $8 * rot_y - $5 * rot_z + $6 * rot_w + $7 * rot_x,
+            rot_z = $8 * rot_z + $5 * rot_y - $6 * rot_x + $7 * rot_w,
+            rot_w = $8 * rot_w - $5 * rot_x - $6 * rot_y - $7 * rot_z,
+            scl_x = scl_x * $9, scl_y = scl_y * $10, scl_z = scl_z * $11,
+            rev = rev + 1, updated_at = now()
+        WHERE id = ANY($1) AND deleted_at IS NULL
+        RETURNING *"#,
+        version.as_str()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_versions_are_accepted() {
+        assert_eq!(sanitize_version("0").unwrap(), "0");
+        assert_eq!(sanitize_version("20240704").unwrap(), "20240704");
+        assert!(sanitize_version(&"9".repeat(MAX_VERSION_LEN)).is_ok());
+    }
+
+    #[test]
+    fn everything_else_is_rejected() {
+        assert!(matches!(sanitize_version(""), Err(VersionError::Empty)));
+        assert!(matches!(
+            sanitize_version(&"9".repeat(MAX_VERSION_LEN + 1)),
+            Err(VersionError::TooLong)
+        ));
+        assert!(matches!(
+            sanitize_version("1; DROP TABLE objects_v1"),
+            Err(VersionError::NotNumeric(';'))
+        ));
+        assert!(matches!(
+            validate_numeric_version("-1"),
+            Err(VersionError::NotNumeric('-'))
+        ));
+        assert!(matches!(
+            validate_numeric_version("１"),
+            Err(VersionError::NotNumeric('１'))
+        ));
+    }
+
+    #[test]
+    fn builders_name_the_level_table() {
+        let table = format!("{}42", table_prefix());
+        assert!(get_objects_sql("42".to_string()).contains(&table));
+        assert!(create_table_sql("42".to_string()).contains(&table));
+    }
+}
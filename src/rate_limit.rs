@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::error::AppError;
+
+/// Number of tracked clients above which idle ones are forgotten.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket per client IP: each client may burst up to one second's
+/// worth of requests, refilled at `per_second`.
+#[derive(Clone)]
+pub struct RateLimit {
+    per_second: f64,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimit {
+    pub fn new(per_second: u32) -> Self {
+        RateLimit {
+            per_second: f64::from(per_second),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes a token for `ip`, returning false if its bucket is empty.
+    fn try_acquire(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        // A bucket idle for a second is full again, the same as a new one.
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| now.duration_since(bucket.refilled_at).as_secs() < 1);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.per_second,
+            refilled_at: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.per_second);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Rejects requests with `429 Too Many Requests` once the client's IP has
+/// used up its budget. Requests without a known peer address pass through.
+pub async fn limit_rate(
+    State(limit): State<RateLimit>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    match peer {
+        Some(ip) if !limit.try_acquire(ip) => Err(AppError::TooManyRequests(format!(
+            "rate limit of {} write requests per second exceeded",
+            limit.per_second
+        ))),
+        _ => Ok(next.run(request).await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn bursts_up_to_the_limit() {
+        let limit = RateLimit::new(3);
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(limit.try_acquire(ip));
+        assert!(limit.try_acquire(ip));
+        assert!(limit.try_acquire(ip));
+        assert!(!limit.try_acquire(ip));
+    }
+
+    #[test]
+    fn clients_have_separate_buckets() {
+        let limit = RateLimit::new(1);
+        let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        assert!(limit.try_acquire(first));
+        assert!(!limit.try_acquire(first));
+        assert!(limit.try_acquire(second));
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let limit = RateLimit::new(1);
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(limit.try_acquire(ip));
+        assert!(!limit.try_acquire(ip));
+        limit
+            .buckets
+            .lock()
+            .unwrap()
+            .get_mut(&ip)
+            .unwrap()
+            .refilled_at -= std::time::Duration::from_secs(1);
+        assert!(limit.try_acquire(ip));
+    }
+}
@@ -0,0 +1,156 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRef, FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use jsonschema::JSONSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use level_server::api::{FieldError, SetLevelObjectRequest, SetLevelObjectsRequest};
+
+use crate::{error::AppError, msgpack::is_msgpack, validation::ObjectTypes};
+
+/// Used when `OBJECT_SCHEMA_PATH` isn't set.
+const DEFAULT_OBJECT_SCHEMA: &str = include_str!("../schemas/level_object.schema.json");
+
+/// JSON Schema every incoming level object must match, checked before the
+/// body is deserialized.
+#[derive(Clone)]
+pub struct ObjectSchema(Arc<JSONSchema>);
+
+impl ObjectSchema {
+    /// Compiles the schema at `path`, or the bundled default.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let text = match path {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("can't read object schema {}", path.display()))?,
+            None => DEFAULT_OBJECT_SCHEMA.to_string(),
+        };
+        let schema: Value = serde_json::from_str(&text).context("object schema isn't JSON")?;
+        let compiled = JSONSchema::compile(&schema)
+            .map_err(|e| anyhow::anyhow!("invalid object schema: {}", e))?;
+        Ok(ObjectSchema(Arc::new(compiled)))
+    }
+
+    /// Records every violation in `object`, with paths prefixed by `at`.
+    fn check(&self, at: &str, object: &Value, errors: &mut Vec<FieldError>) {
+        if let Err(violations) = self.0.validate(object) {
+            errors.extend(violations.map(|e| {
+                let path = format!("{}{}", at, e.instance_path);
+                // The empty pointer is the whole object; `/` reads better.
+                let path = if path.is_empty() {
+                    "/".to_string()
+                } else {
+                    path
+                };
+                FieldError::new(path, e.to_string())
+            }));
+        }
+    }
+}
+
+/// Request bodies made of level objects, telling where the objects are.
+pub trait HasObjects: DeserializeOwned {
+    fn check_objects(
+        schema: &ObjectSchema,
+        types: &ObjectTypes,
+        body: &Value,
+        errors: &mut Vec<FieldError>,
+    );
+}
+
+impl HasObjects for SetLevelObjectRequest {
+    fn check_objects(
+        schema: &ObjectSchema,
+        types: &ObjectTypes,
+        body: &Value,
+        errors: &mut Vec<FieldError>,
+    ) {
+        // `object_type` may be left out here, so check the object the
+        // handler will actually store.
+        match body {
+            Value::Object(object) if !object.contains_key("object_type") => {
+                let mut object = object.clone();
+                object.insert("object_type".to_string(), types.default_type().into());
+                schema.check("", &Value::Object(object), errors);
+            }
+            _ => schema.check("", body, errors),
+        }
+    }
+}
+
+impl HasObjects for SetLevelObjectsRequest {
+    fn check_objects(
+        schema: &ObjectSchema,
+        _types: &ObjectTypes,
+        body: &Value,
+        errors: &mut Vec<FieldError>,
+    ) {
+        if let Some(objects) = body.get("objects").and_then(Value::as_array) {
+            for (index, object) in objects.iter().enumerate() {
+                schema.check(&format!("/objects/{}", index), object, errors);
+            }
+        }
+    }
+}
+
+/// Like `Json<T>`, but rejects bodies whose objects don't match the
+/// `ObjectSchema` with a 422 naming the JSON path of each violation. Bodies
+/// sent as `application/msgpack` are decoded and checked the same way.
+pub struct SchemaJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for SchemaJson<T>
+where
+    T: HasObjects,
+    ObjectSchema: FromRef<S>,
+    ObjectTypes: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let body = if is_msgpack(req.headers()) {
+            let bytes = Bytes::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            rmp_serde::from_slice::<Value>(&bytes).map_err(|e| {
+                AppError::Validation(format!("invalid MessagePack body: {}", e)).into_response()
+            })?
+        } else {
+            let Json(body) = Json::<Value>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            body
+        };
+
+        let mut errors = Vec::new();
+        T::check_objects(
+            &ObjectSchema::from_ref(state),
+            &ObjectTypes::from_ref(state),
+            &body,
+            &mut errors,
+        );
+        if !errors.is_empty() {
+            return Err(AppError::SchemaViolation(errors).into_response());
+        }
+
+        // Same response `Json<T>` gives for a body of the wrong shape.
+        serde_json::from_value(body).map(SchemaJson).map_err(|e| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "Failed to deserialize the JSON body into the target type: {}",
+                    e
+                ),
+            )
+                .into_response()
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use std::{sync::OnceLock, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+
+const REQUESTS_TOTAL: &str = "http_requests_total";
+const REQUEST_ERRORS_TOTAL: &str = "http_request_errors_total";
+const REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+
+/// Latency buckets in seconds, from a fast indexed lookup up to a slow bulk
+/// upload.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Installs the global Prometheus recorder on first use. The returned handle
+/// renders the text exposition served on `/metrics`; later calls return the
+/// same handle, so a router can be built more than once.
+pub fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    if let Some(handle) = HANDLE.get() {
+        return Ok(handle.clone());
+    }
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(REQUEST_DURATION_SECONDS.to_string()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+    Ok(HANDLE.get_or_init(|| handle).clone())
+}
+
+/// Records a count and latency for every request, labelled by route template
+/// and status class so ids in query strings don't explode cardinality.
+pub async fn track_metrics(request: Request, next: Next) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string(),
+    };
+    let method = request.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let status = response.status();
+    let status_class = format!("{}xx", status.as_u16() / 100);
+    let labels = [
+        ("route", route),
+        ("method", method),
+        ("status", status_class),
+    ];
+
+    metrics::counter!(REQUESTS_TOTAL, &labels).increment(1);
+    metrics::histogram!(REQUEST_DURATION_SECONDS, &labels).record(elapsed);
+    if status.is_client_error() || status.is_server_error() {
+        metrics::counter!(REQUEST_ERRORS_TOTAL, &labels).increment(1);
+    }
+
+    response
+}
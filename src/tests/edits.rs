@@ -0,0 +1,541 @@
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::*;
+
+fn update(id: &Value, x: f64, rev: Option<i64>) -> Value {
+    json!({
+        "version": "1",
+        "id": id,
+        "object_type": "light",
+        "position": format!("{},0,0", x),
+        "rotation": "0,0,0,1",
+        "scale": "2,2,2",
+        "collider": "null",
+        "rev": rev,
+    })
+}
+
+#[sqlx::test(migrations = false)]
+async fn update_replaces_the_object(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+
+    let (status, body) = post(&app, "/update-object", update(&object["id"], 3.0, None)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["object_type"], "light");
+    assert_eq!(body["position"]["x"], 3.0);
+    assert_eq!(body["scale"]["x"], 2.0);
+    assert_eq!(body["rev"], 2);
+    assert_eq!(body["created_at"], object["created_at"]);
+    assert_ne!(body["updated_at"], object["updated_at"]);
+
+    let (status, _) = post(&app, "/update-object", update(&json!(99), 3.0, None)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn stale_rev_is_a_conflict(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+
+    let (status, _) = post(&app, "/update-object", update(&object["id"], 1.0, Some(1))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = post(&app, "/update-object", update(&object["id"], 2.0, Some(1))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body["error"].as_str().unwrap().contains("rev 2"));
+
+    let (status, _) = post(
+        &app,
+        "/patch-object",
+        json!({"version": "1", "id": object["id"], "scale": "3,3,3", "rev": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[sqlx::test(migrations = false)]
+async fn patch_changes_only_given_fields(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let mut body = new_object("1", "prop", 4.0);
+    body["tags"] = json!(["keep"]);
+    let (_, body) = post(&app, "/set-object", body).await;
+    let object = body["object"].clone();
+
+    let (status, body) = post(
+        &app,
+        "/patch-object",
+        json!({"version": "1", "id": object["id"], "scale": "3,3,3"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["scale"], json!({"x": 3.0, "y": 3.0, "z": 3.0}));
+    for field in ["object_type", "position", "rotation", "collider", "tags"] {
+        assert_eq!(body[field], object[field], "{}", field);
+    }
+
+    let (status, _) = post(
+        &app,
+        "/patch-object",
+        json!({"version": "1", "id": object["id"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn move_and_scale_keep_other_fields(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "light", 0.0).await;
+
+    let (status, moved) = post(
+        &app,
+        "/move-object",
+        json!({"version": "1", "id": object["id"], "position": "5,6,7", "rotation": "0,1,0,0"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["position"], json!({"x": 5.0, "y": 6.0, "z": 7.0}));
+    assert_eq!(moved["scale"], object["scale"]);
+    assert_eq!(moved["object_type"], "light");
+
+    let (status, scaled) = post(
+        &app,
+        "/scale-object",
+        json!({"version": "1", "id": object["id"], "scale": "2,2,2"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(scaled["position"], moved["position"]);
+    assert_eq!(scaled["scale"]["y"], 2.0);
+
+    let (status, _) = post(
+        &app,
+        "/scale-object",
+        json!({"version": "1", "id": object["id"], "scale": "0,1,1"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn touch_advances_updated_at_only(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+
+    let (status, touched) = post(
+        &app,
+        &format!("/touch-object?version=1&id={}", object["id"]),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(time(&touched["updated_at"]) > time(&object["updated_at"]));
+    assert_eq!(touched["rev"], object["rev"]);
+    assert_eq!(touched["position"], object["position"]);
+
+    let (status, _) = post(&app, "/touch-object?version=1&id=99", Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn transform_moves_a_group(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let a = set_object(&app, "1", "prop", 0.0).await;
+    let b = set_object(&app, "1", "prop", 1.0).await;
+    let untouched = set_object(&app, "1", "prop", 2.0).await;
+
+    let (status, body) = post(
+        &app,
+        "/transform-objects",
+        json!({
+            "version": "1",
+            "ids": [a["id"], b["id"], 99],
+            "delta_position": "0,10,0",
+            "scale_multiplier": "2,2,2",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["rows_affected"], 2);
+
+    let (_, body) = get(&app, "/get-objects?version=1").await;
+    let objects = body["objects"].as_array().unwrap();
+    assert_eq!(
+        objects[0]["position"],
+        json!({"x": 0.0, "y": 10.0, "z": 0.0})
+    );
+    assert_eq!(
+        objects[1]["position"],
+        json!({"x": 1.0, "y": 10.0, "z": 0.0})
+    );
+    assert_eq!(objects[1]["scale"], json!({"x": 2.0, "y": 2.0, "z": 2.0}));
+    assert_eq!(objects[2]["position"], untouched["position"]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn delete_objects_removes_a_subset(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..4 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+
+    let (status, body) = post(
+        &app,
+        "/delete-objects",
+        json!({"version": "1", "ids": [1, 3]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["rows_affected"], 2);
+    assert_eq!(object_ids(&app, "1").await, [2, 4]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn soft_deleted_objects_can_be_restored(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+
+    let (status, _) = delete(
+        &app,
+        &format!("/delete-object?version=1&id={}", object["id"]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(object_ids(&app, "1").await.is_empty());
+    let (status, _) = delete(
+        &app,
+        &format!("/delete-object?version=1&id={}", object["id"]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = post(
+        &app,
+        "/restore-object",
+        json!({"version": "1", "id": object["id"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], object["id"]);
+    assert_eq!(object_ids(&app, "1").await, [1]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn duplicate_copies_fields_with_a_new_id(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let mut body = new_object("1", "light", 2.0);
+    body["tags"] = json!(["lamp"]);
+    post(&app, "/set-object", body).await;
+    let (_, object) = get(&app, "/get-object?version=1&id=1").await;
+
+    let (status, copy) = post(
+        &app,
+        "/duplicate-object",
+        json!({"version": "1", "id": object["id"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(copy["id"], object["id"]);
+    for field in [
+        "object_type",
+        "position",
+        "rotation",
+        "scale",
+        "collider",
+        "tags",
+    ] {
+        assert_eq!(copy[field], object[field], "{}", field);
+    }
+}
+
+#[sqlx::test(migrations = false)]
+async fn batch_applies_every_operation_or_none(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let kept = set_object(&app, "1", "prop", 0.0).await;
+    let doomed = set_object(&app, "1", "prop", 1.0).await;
+
+    let mut changed = update(&kept["id"], 5.0, Some(1));
+    changed["op"] = json!("update");
+    let (status, body) = post(
+        &app,
+        "/batch",
+        json!({
+            "version": "1",
+            "operations": [
+                {
+                    "op": "insert",
+                    "object_type": "spawn",
+                    "position": "9,0,0",
+                    "rotation": "0,0,0,1",
+                    "scale": "1,1,1",
+                    "collider": "null",
+                },
+                changed,
+                {"op": "delete", "id": doomed["id"]},
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let ops: Vec<_> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["op"].as_str().unwrap())
+        .collect();
+    assert_eq!(ops, ["insert", "update", "delete"]);
+    assert_eq!(object_ids(&app, "1").await, [1, 3]);
+
+    // The stale rev fails the second operation, so the insert is rolled back.
+    let (status, body) = post(
+        &app,
+        "/batch",
+        json!({
+            "version": "1",
+            "operations": [
+                {
+                    "op": "insert",
+                    "object_type": "spawn",
+                    "position": "9,0,0",
+                    "rotation": "0,0,0,1",
+                    "scale": "1,1,1",
+                    "collider": "null",
+                },
+                changed,
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body["error"].as_str().unwrap().starts_with("operations[1]"));
+    assert_eq!(object_ids(&app, "1").await, [1, 3]);
+
+    // An object inserted earlier in the batch is found when explaining why
+    // its update failed. The rolled back insert used up id 4.
+    let mut stale = update(&json!(5), 5.0, Some(7));
+    stale["op"] = json!("update");
+    let (status, body) = post(
+        &app,
+        "/batch",
+        json!({
+            "version": "1",
+            "operations": [
+                {
+                    "op": "insert",
+                    "object_type": "spawn",
+                    "position": "9,0,0",
+                    "rotation": "0,0,0,1",
+                    "scale": "1,1,1",
+                    "collider": "null",
+                },
+                stale,
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+}
+
+#[sqlx::test(migrations = false)]
+async fn locks_block_other_editors_until_they_expire(pool: PgPool) {
+    let app = test_app(pool.clone()).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+    let lock = |editor: &str| json!({"version": "1", "id": object["id"], "editor": editor});
+
+    let (status, body) = post(&app, "/lock-object", lock("ada")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["locked_by"], "ada");
+
+    let (status, body) = post(&app, "/lock-object", lock("bob")).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    assert!(body["error"].as_str().unwrap().contains("ada"));
+
+    let mut edit = update(&object["id"], 1.0, None);
+    edit["editor"] = json!("bob");
+    let (status, _) = post(&app, "/update-object", edit.clone()).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    edit["editor"] = json!("ada");
+    let (status, _) = post(&app, "/update-object", edit.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    sqlx::query("UPDATE objects_v1 SET locked_at = now() - interval '1 day'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, body) = post(&app, "/lock-object", lock("bob")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["locked_by"], "bob");
+
+    let (status, _) = post(&app, "/unlock-object", lock("ada")).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    let (status, body) = post(&app, "/unlock-object", lock("bob")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["locked_by"].is_null());
+}
+
+#[sqlx::test(migrations = false)]
+async fn locks_are_checked_on_every_write(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let id = set_object(&app, "1", "prop", 0.0).await["id"].clone();
+    let (status, _) = post(
+        &app,
+        "/lock-object",
+        json!({"version": "1", "id": id, "editor": "ada"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let writes = [
+        (
+            "/set-object",
+            json!({
+                "version": "1",
+                "id": id,
+                "object_type": "light",
+                "position": "0,0,0",
+                "rotation": "0,0,0,1",
+                "scale": "1,1,1",
+                "collider": "null",
+            }),
+        ),
+        (
+            "/patch-object",
+            json!({"version": "1", "id": id, "scale": "3,3,3"}),
+        ),
+        (
+            "/move-object",
+            json!({"version": "1", "id": id, "position": "1,0,0", "rotation": "0,0,0,1"}),
+        ),
+        (
+            "/scale-object",
+            json!({"version": "1", "id": id, "scale": "2,2,2"}),
+        ),
+        (
+            "/transform-objects",
+            json!({"version": "1", "ids": [id], "delta_position": "0,1,0"}),
+        ),
+        (
+            "/batch",
+            json!({"version": "1", "operations": [{
+                "op": "update",
+                "id": id,
+                "object_type": "light",
+                "position": "0,0,0",
+                "rotation": "0,0,0,1",
+                "scale": "1,1,1",
+                "collider": "null",
+            }]}),
+        ),
+    ];
+    for (uri, body) in writes {
+        let (status, _) = post(&app, uri, body.clone()).await;
+        assert_eq!(status, StatusCode::LOCKED, "{} without an editor", uri);
+        let mut as_bob = body.clone();
+        as_bob["editor"] = json!("bob");
+        let (status, _) = post(&app, uri, as_bob).await;
+        assert_eq!(status, StatusCode::LOCKED, "{} as bob", uri);
+        let mut as_ada = body;
+        as_ada["editor"] = json!("ada");
+        let (status, body) = post(&app, uri, as_ada).await;
+        assert_eq!(status, StatusCode::OK, "{} as ada: {}", uri, body);
+    }
+
+    let batch_delete = |editor: &str| json!({"version": "1", "editor": editor, "operations": [{"op": "delete", "id": id}]});
+    let (status, body) = post(&app, "/batch", batch_delete("bob")).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    assert!(body["error"].as_str().unwrap().starts_with("operations[0]"));
+    let (status, _) = post(
+        &app,
+        "/delete-objects",
+        json!({"version": "1", "ids": [id], "editor": "bob"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::LOCKED);
+    let (status, _) = delete(
+        &app,
+        &format!("/delete-object?version=1&id={id}&editor=bob"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::LOCKED);
+    assert_eq!(object_ids(&app, "1").await, [1]);
+
+    let (status, _) = delete(
+        &app,
+        &format!("/delete-object?version=1&id={id}&editor=ada"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Soft deleting keeps the lock, so restoring is refused too.
+    let restore = |editor: &str| json!({"version": "1", "id": id, "editor": editor});
+    let (status, _) = post(&app, "/restore-object", restore("bob")).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    let (status, _) = post(&app, "/restore-object", restore("ada")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = false)]
+async fn idempotency_key_replays_the_first_result(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+
+    let request = || {
+        let mut request = json_request(Method::POST, "/set-object", &new_object("1", "prop", 0.0));
+        request
+            .headers_mut()
+            .insert("idempotency-key", "place-lamp-1".parse().unwrap());
+        request
+    };
+    let (status, first) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::OK, "{}", first);
+    let (status, second) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first, second);
+    assert_eq!(object_ids(&app, "1").await, [1]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn max_objects_is_enforced_at_the_boundary(pool: PgPool) {
+    let app = app_with(pool, &[("MAX_OBJECTS_PER_LEVEL", "2")]).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+
+    let (status, _) = post(&app, "/set-object", new_object("1", "prop", 2.0)).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert_eq!(object_ids(&app, "1").await, [1, 2]);
+
+    let (status, _) = post(&app, "/duplicate-object", json!({"version": "1", "id": 1})).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+
+    // Deleting makes room again.
+    delete(&app, "/delete-object?version=1&id=1").await;
+    let (status, _) = post(&app, "/set-object", new_object("1", "prop", 2.0)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[test]
+fn max_objects_check() {
+    use crate::MaxObjects;
+
+    assert!(MaxObjects(None).check("1", i64::MAX, 1).is_ok());
+    assert!(MaxObjects(Some(10)).check("1", 10, 1).is_ok());
+    assert!(matches!(
+        MaxObjects(Some(10)).check("1", 11, 2),
+        Err(crate::AppError::InsufficientStorage(message)) if message.contains("from 9 to 11")
+    ));
+}
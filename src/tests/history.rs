@@ -0,0 +1,162 @@
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::*;
+
+async fn undo(app: &Router) -> (StatusCode, Value) {
+    post(app, "/undo", json!({"version": "1"})).await
+}
+
+#[sqlx::test(migrations = false)]
+async fn undo_reverts_inserts_updates_and_deletes(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let kept = set_object(&app, "1", "prop", 0.0).await;
+    let added = set_object(&app, "1", "prop", 1.0).await;
+
+    let (status, body) = undo(&app).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body["undone"],
+        json!([{"id": added["id"], "operation": "insert"}])
+    );
+    assert_eq!(object_ids(&app, "1").await, [1]);
+
+    post(
+        &app,
+        "/move-object",
+        json!({"version": "1", "id": kept["id"], "position": "5,5,5", "rotation": "0,0,0,1"}),
+    )
+    .await;
+    let (status, _) = undo(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = get(&app, "/get-object?version=1&id=1").await;
+    assert_eq!(body["position"], kept["position"]);
+
+    delete(&app, "/delete-object?version=1&id=1&hard=true").await;
+    assert!(object_ids(&app, "1").await.is_empty());
+    let (status, _) = undo(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(object_ids(&app, "1").await, [1]);
+
+    // Only the inserts are left, and after them nothing.
+    let (status, _) = undo(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = undo(&app).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn snapshot_restores_after_later_edits(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "light", 1.0).await;
+
+    let (status, snapshot) = post(&app, "/snapshot?version=1", Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", snapshot);
+    assert_eq!(snapshot["object_count"], 2);
+    let (_, before) = get(&app, "/get-objects?version=1").await;
+
+    delete(&app, "/delete-object?version=1&id=1&hard=true").await;
+    post(
+        &app,
+        "/move-object",
+        json!({"version": "1", "id": 2, "position": "9,9,9", "rotation": "0,0,0,1"}),
+    )
+    .await;
+    set_object(&app, "1", "spawn", 3.0).await;
+
+    let (status, body) = post(
+        &app,
+        "/restore-snapshot",
+        json!({"version": "1", "snapshot_id": snapshot["snapshot_id"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 2);
+    let (_, after) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(after["objects"], before["objects"]);
+
+    let (_, body) = get(&app, "/snapshots?version=1").await;
+    assert_eq!(body["snapshots"].as_array().unwrap().len(), 1);
+    let (status, _) = post(
+        &app,
+        "/restore-snapshot",
+        json!({"version": "1", "snapshot_id": 999}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn audit_lists_changes_newest_first(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+    delete(&app, "/delete-object?version=1&id=1&hard=true").await;
+
+    let (status, body) = get(&app, "/audit?version=1").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let entries: Vec<_> = body["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["operation"].as_str().unwrap(),
+                e["object_id"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(entries, [("delete", 1), ("insert", 2), ("insert", 1)]);
+
+    let (_, body) = get(&app, "/audit?version=1&operation=insert&limit=1&offset=1").await;
+    assert_eq!(body["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(body["entries"][0]["object_id"], 1);
+}
+
+#[sqlx::test(migrations = false)]
+async fn audit_records_the_editor_and_soft_deletes(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    let mut overwrite = new_object("1", "light", 0.0);
+    overwrite["id"] = json!(1);
+    overwrite["editor"] = json!("cy");
+    let (status, _) = post(&app, "/set-object", overwrite).await;
+    assert_eq!(status, StatusCode::OK);
+    post(
+        &app,
+        "/scale-object",
+        json!({"version": "1", "id": 1, "scale": "2,2,2", "editor": "ada"}),
+    )
+    .await;
+    let (status, _) = delete(&app, "/delete-object?version=1&id=1&editor=bob").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = get(&app, "/audit?version=1").await;
+    let entries: Vec<_> = body["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["operation"].as_str().unwrap(), e["actor"].as_str()))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("delete", Some("bob")),
+            ("update", Some("ada")),
+            ("update", Some("cy")),
+            ("insert", None)
+        ]
+    );
+
+    // Undoing the soft delete brings the row back.
+    let (status, body) = undo(&app).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["undone"], json!([{"id": 1, "operation": "delete"}]));
+    assert_eq!(object_ids(&app, "1").await, [1]);
+}
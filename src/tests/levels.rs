@@ -0,0 +1,400 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::*;
+
+#[sqlx::test(migrations = false)]
+async fn prepare_reports_deleted_rows(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..3 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+    delete(&app, "/delete-object?version=1&id=3").await;
+
+    let (status, body) = get(&app, "/prepare?version=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 0);
+    assert_eq!(body["deleted_count"], 3);
+}
+
+#[sqlx::test(migrations = false)]
+async fn prepare_is_marked_deprecated(pool: PgPool) {
+    let app = test_app(pool).await;
+    let response = app
+        .oneshot(
+            Request::get("/prepare?version=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["deprecation"], "true");
+    assert_eq!(
+        response.headers()["sunset"],
+        "Sun, 31 Jan 2027 00:00:00 GMT"
+    );
+}
+
+#[sqlx::test(migrations = false)]
+async fn prepare_dry_run_deletes_nothing(pool: PgPool) {
+    let app = test_app(pool).await;
+    let (_, body) = get(&app, "/prepare?version=1&dry_run=true").await;
+    assert_eq!(body, json!({"table_exists": false, "count": 0}));
+
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+    set_object(&app, "1", "prop", 2.0).await;
+    delete(&app, "/delete-object?version=1&id=3").await;
+    let (status, body) = get(&app, "/prepare?version=1&dry_run=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"table_exists": true, "count": 3}));
+    assert_eq!(object_ids(&app, "1").await, [1, 2]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn create_level_keeps_existing_rows(pool: PgPool) {
+    let app = test_app(pool).await;
+    let (_, body) = post(&app, "/create-level?version=1", Value::Null).await;
+    assert_eq!(body["created"], true);
+    set_object(&app, "1", "prop", 0.0).await;
+
+    let (status, body) = post(&app, "/create-level?version=1", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["created"], false);
+    assert_eq!(object_ids(&app, "1").await, [1]);
+
+    let (_, body) = post(&app, "/clear-level?version=1", Value::Null).await;
+    assert_eq!(body["rows_affected"], 1);
+    assert!(object_ids(&app, "1").await.is_empty());
+}
+
+#[sqlx::test(migrations = false)]
+async fn levels_index_object_type(pool: PgPool) {
+    let app = test_app(pool.clone()).await;
+    prepare(&app, "1").await;
+    let indexed: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM pg_indexes WHERE indexname = 'idx_objects_v1_type')",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(indexed);
+}
+
+#[sqlx::test(migrations = false)]
+async fn versions_lists_every_level(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "3").await;
+    prepare(&app, "12").await;
+    let (status, body) = get(&app, "/versions").await;
+    assert_eq!(status, StatusCode::OK);
+    let mut versions: Vec<_> = body["versions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_str().unwrap().to_string())
+        .collect();
+    versions.sort();
+    assert_eq!(versions, ["12", "3"]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn delete_level_drops_the_level(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+
+    let (status, _) = post(&app, "/delete-level?version=1", Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = post(&app, "/delete-level?version=1&confirm=true", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["existed"], true);
+
+    let (_, body) = get(&app, "/versions").await;
+    assert_eq!(body["versions"], json!([]));
+    let (status, _) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn import_replaces_the_level(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+
+    let (status, body) = post(
+        &app,
+        "/import-level",
+        json!({
+            "version": "1",
+            "objects": [{
+                "object_type": "spawn",
+                "position": "7,0,0",
+                "rotation": "0,0,0,1",
+                "scale": "1,1,1",
+                "collider": "null",
+            }],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 1);
+    let (_, body) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["objects"][0]["object_type"], "spawn");
+}
+
+#[sqlx::test(migrations = false)]
+async fn export_round_trips_through_import(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let mut tagged = new_object("1", "light", 1.0);
+    tagged["tags"] = json!(["warm"]);
+    tagged["collider"] = json!(r#"{"type": "sphere", "radius": 1.5}"#);
+    post(&app, "/set-object", tagged).await;
+    set_object(&app, "1", "prop", 2.0).await;
+
+    let (status, mut document) = get(&app, "/export-level?version=1").await;
+    assert_eq!(status, StatusCode::OK);
+    document["version"] = json!("2");
+    let (status, body) = post(&app, "/import-level", document).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (_, exported) = get(&app, "/export-level?version=1").await;
+    let (_, imported) = get(&app, "/export-level?version=2").await;
+    assert_eq!(imported["objects"], exported["objects"]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn copy_level_clones_into_an_empty_level(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "light", 1.0).await;
+
+    let copy = json!({"from_version": "1", "to_version": "2"});
+    let (status, body) = post(&app, "/copy-level", copy.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 2);
+    assert_eq!(object_ids(&app, "2").await, [1, 2]);
+
+    let (status, _) = post(&app, "/copy-level", copy).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[sqlx::test(migrations = false)]
+async fn diff_finds_added_and_changed_objects(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+    post(
+        &app,
+        "/copy-level",
+        json!({"from_version": "1", "to_version": "2"}),
+    )
+    .await;
+    set_object(&app, "2", "light", 5.0).await;
+    post(
+        &app,
+        "/move-object",
+        json!({"version": "2", "id": 2, "position": "1,1,1", "rotation": "0,0,0,1"}),
+    )
+    .await;
+
+    let (status, body) = get(&app, "/diff?from=1&to=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["added"].as_array().unwrap().len(), 1);
+    assert_eq!(body["added"][0]["object_type"], "light");
+    assert_eq!(body["removed"], json!([]));
+    assert_eq!(body["changed"][0]["id"], 2);
+    assert_eq!(body["changed"][0]["fields"], json!(["position"]));
+
+    let (status, _) = get(&app, "/diff?from=1&to=9").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn metadata_is_created_and_updated(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let (status, _) = get(&app, "/level-metadata?version=1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = post(
+        &app,
+        "/level-metadata",
+        json!({"version": "1", "name": "Harbor", "author": "ada"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["name"], "Harbor");
+
+    let (_, body) = post(
+        &app,
+        "/level-metadata",
+        json!({"version": "1", "description": "Night version"}),
+    )
+    .await;
+    assert_eq!(body["name"], "Harbor");
+    assert_eq!(body["description"], "Night version");
+    let (_, fetched) = get(&app, "/level-metadata?version=1").await;
+    assert_eq!(fetched, body);
+
+    let (status, _) = post(
+        &app,
+        "/level-metadata",
+        json!({"version": "2", "name": "Nowhere"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn level_updated_at_advances_on_writes(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let (_, body) = get(&app, "/level-updated-at?version=1").await;
+    assert!(body["updated_at"].is_null());
+
+    set_object(&app, "1", "prop", 0.0).await;
+    let (_, first) = get(&app, "/level-updated-at?version=1").await;
+    set_object(&app, "1", "prop", 1.0).await;
+    let (_, second) = get(&app, "/level-updated-at?version=1").await;
+    assert!(time(&second["updated_at"]) > time(&first["updated_at"]));
+}
+
+#[sqlx::test(migrations = false)]
+async fn level_size_counts_stored_rows(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+    delete(&app, "/delete-object?version=1&id=1").await;
+
+    let (status, body) = get(&app, "/level-size?version=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["size_bytes"].as_i64().unwrap() > 0);
+    assert_eq!(body["row_count"], 2);
+
+    let (status, _) = get(&app, "/level-size?version=2").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn compact_makes_ids_contiguous(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..5 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+    post(
+        &app,
+        "/delete-objects",
+        json!({"version": "1", "ids": [1, 3], "hard": true}),
+    )
+    .await;
+
+    let (status, _) = post(&app, "/compact?version=1", Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = post(&app, "/compact?version=1&confirm=true", Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 3);
+    assert_eq!(object_ids(&app, "1").await, [1, 2, 3]);
+    let (_, body) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(body["objects"][0]["position"]["x"], 1.0);
+
+    // New objects continue after the renumbered ones.
+    let object = set_object(&app, "1", "prop", 9.0).await;
+    assert_eq!(object["id"], 4);
+}
+
+/// A level table as the first release created it.
+async fn create_legacy_level(pool: &PgPool, version: &str) {
+    sqlx::query(&format!(
+        "CREATE TABLE objects_v{} (id SERIAL PRIMARY KEY, object_type VARCHAR(255) NOT NULL, position VARCHAR(255) NOT NULL, scale VARCHAR(255) NOT NULL, rotation VARCHAR(255) NOT NULL, collider TEXT NOT NULL)",
+        version
+    ))
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(&format!(
+        "INSERT INTO objects_v{} (object_type, position, scale, rotation, collider) VALUES ('prop', '1,2,3', '1,1,1', '0,0,0,1', 'null')",
+        version
+    ))
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[sqlx::test(migrations = false)]
+async fn legacy_tables_are_upgraded_at_startup(pool: PgPool) {
+    migrate(&pool).await;
+    create_legacy_level(&pool, "4").await;
+    for environment in Environments::new(pool.clone(), BTreeMap::new()).all() {
+        crate::upgrade_tables(environment).await;
+    }
+
+    let app = test_app(pool).await;
+    let (status, body) = get(&app, "/get-objects?version=4").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["objects"][0]["position"]["z"], 3.0);
+}
+
+#[sqlx::test(migrations = false)]
+async fn legacy_tables_are_upgraded_on_first_write(pool: PgPool) {
+    let app = test_app(pool.clone()).await;
+    create_legacy_level(&pool, "5").await;
+
+    // Concurrent first writes each find the table not yet upgraded.
+    let writes = (0..4).map(|x| {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let (status, body) = post(&app, "/set-object", new_object("5", "prop", x.into())).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+        })
+    });
+    for write in writes.collect::<Vec<_>>() {
+        write.await.unwrap();
+    }
+    assert_eq!(object_ids(&app, "5").await, [1, 2, 3, 4, 5]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn legacy_tables_are_upgraded_by_import_and_copy(pool: PgPool) {
+    let app = test_app(pool.clone()).await;
+    create_legacy_level(&pool, "6").await;
+    create_legacy_level(&pool, "7").await;
+
+    let (status, body) = post(
+        &app,
+        "/import-level",
+        json!({"version": "6", "objects": [{
+            "object_type": "prop",
+            "position": "1,0,0",
+            "rotation": "0,0,0,1",
+            "scale": "1,1,1",
+            "collider": "null",
+        }]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 1);
+
+    let (status, body) = post(
+        &app,
+        "/copy-level",
+        json!({"from_version": "6", "to_version": "7", "overwrite": true}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 1);
+}
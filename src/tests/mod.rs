@@ -0,0 +1,133 @@
+//! Tests that drive the router the way clients do. `#[sqlx::test]` gives
+//! every test a fresh database, which needs `DATABASE_URL` to point at a
+//! server where it may create databases; `migrate` then sets it up.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+use crate::{app, config::Config, environments::Environments, MIGRATOR};
+
+mod edits;
+mod history;
+mod levels;
+mod objects;
+mod system;
+
+/// Brings a test database up to the current schema. The first migration
+/// patches the `objects_v0` table of the original deployment, so a stand-in
+/// for it is made beforehand and dropped again afterwards.
+async fn migrate(pool: &PgPool) {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS objects_v0 (id SERIAL PRIMARY KEY, object_type INTEGER, color INTEGER, position TEXT, size TEXT)",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    MIGRATOR.run(pool).await.expect("can't run the migrations");
+    sqlx::query("DROP TABLE objects_v0")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// The router as `main` builds it, serving the migrated `pool` with the
+/// default settings overridden by `vars`.
+async fn app_with(pool: PgPool, vars: &[(&str, &str)]) -> Router {
+    migrate(&pool).await;
+    let config = Config::from_vars(
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+    )
+    .expect("invalid test config");
+    app(Environments::new(pool, BTreeMap::new()), &config).expect("can't build the router")
+}
+
+async fn test_app(pool: PgPool) -> Router {
+    app_with(pool, &[]).await
+}
+
+/// Sends `request` and reads the body as JSON, or `Value::Null` when it's
+/// empty or not JSON.
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn post(app: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+    send(app, json_request(Method::POST, uri, &body)).await
+}
+
+async fn delete(app: &Router, uri: &str) -> (StatusCode, Value) {
+    send(app, Request::delete(uri).body(Body::empty()).unwrap()).await
+}
+
+fn json_request(method: Method, uri: &str, body: &Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Creates level `version`.
+async fn prepare(app: &Router, version: &str) {
+    let (status, body) = post(
+        app,
+        &format!("/create-level?version={}", version),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+/// A valid `/set-object` body at `x` on the x axis.
+fn new_object(version: &str, object_type: &str, x: f64) -> Value {
+    json!({
+        "version": version,
+        "object_type": object_type,
+        "position": format!("{},0,0", x),
+        "rotation": "0,0,0,1",
+        "scale": "1,1,1",
+        "collider": "null",
+    })
+}
+
+/// Stores an object and returns it as stored.
+async fn set_object(app: &Router, version: &str, object_type: &str, x: f64) -> Value {
+    let (status, body) = post(app, "/set-object", new_object(version, object_type, x)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["object"].clone()
+}
+
+/// A timestamp field of a response.
+fn time(value: &Value) -> DateTime<Utc> {
+    value.as_str().unwrap().parse().unwrap()
+}
+
+/// Ids of the live objects of a level, in id order.
+async fn object_ids(app: &Router, version: &str) -> Vec<i64> {
+    let (status, body) = get(app, &format!("/get-objects?version={}", version)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|object| object["id"].as_i64().unwrap())
+        .collect()
+}
@@ -0,0 +1,752 @@
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use level_server::api::LevelObject;
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::*;
+
+#[sqlx::test(migrations = false)]
+async fn set_object_round_trips_through_get_objects(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+
+    let (status, body) = post(
+        &app,
+        "/set-object",
+        json!({
+            "version": "1",
+            "object_type": "light",
+            "position": "(1.5, 2, -3)",
+            "rotation": "0,0.6,0,0.8",
+            "scale": "2,2,2",
+            "collider": r#"{"type": "sphere", "radius": 0.5}"#,
+            "tags": ["lobby"],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 1);
+
+    let (status, body) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 1);
+    let object = &body["objects"][0];
+    assert_eq!(object["id"], 1);
+    assert_eq!(object["object_type"], "light");
+    assert_eq!(object["position"], json!({"x": 1.5, "y": 2.0, "z": -3.0}));
+    assert_eq!(
+        object["rotation"],
+        json!({"x": 0.0, "y": 0.6, "z": 0.0, "w": 0.8})
+    );
+    assert_eq!(object["scale"], json!({"x": 2.0, "y": 2.0, "z": 2.0}));
+    assert_eq!(object["collider"], json!({"type": "sphere", "radius": 0.5}));
+    assert_eq!(object["tags"], json!(["lobby"]));
+    assert_eq!(object["rev"], 1);
+}
+
+#[sqlx::test(migrations = false)]
+async fn response_field_names_are_pinned(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+
+    let mut fields: Vec<_> = object.as_object().unwrap().keys().cloned().collect();
+    fields.sort();
+    assert_eq!(
+        fields,
+        [
+            "collider",
+            "created_at",
+            "id",
+            "object_type",
+            "position",
+            "rev",
+            "rotation",
+            "scale",
+            "tags",
+            "updated_at"
+        ]
+    );
+    assert_eq!(object["position"], json!({"x": 0.0, "y": 0.0, "z": 0.0}));
+    assert!(object["created_at"].is_string());
+}
+
+#[sqlx::test(migrations = false)]
+async fn returned_id_matches_the_stored_row(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    let object = set_object(&app, "1", "spawn", 1.0).await;
+
+    let (status, body) = get(&app, &format!("/get-object?version=1&id={}", object["id"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["object_type"], "spawn");
+    assert_eq!(body, object);
+}
+
+#[sqlx::test(migrations = false)]
+async fn injected_versions_are_rejected(pool: PgPool) {
+    let app = test_app(pool).await;
+    for version in ["1;DROP TABLE objects_v1", "1 OR 1=1", "abc", "1%27", ""] {
+        let encoded = version.replace(' ', "%20").replace(';', "%3B");
+        let (status, _) = get(&app, &format!("/get-object?version={}&id=1", encoded)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", version);
+        let (status, _) = get(&app, &format!("/prepare?version={}", encoded)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", version);
+        let (status, _) = post(&app, "/set-object", new_object(version, "prop", 0.0)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", version);
+    }
+    let (_, body) = get(&app, "/versions").await;
+    assert_eq!(body["versions"], json!([]));
+}
+
+#[sqlx::test(migrations = false)]
+async fn unknown_object_is_not_found(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let (status, body) = get(&app, "/get-object?version=1&id=42").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"].as_str().unwrap().contains("42"));
+}
+
+#[sqlx::test(migrations = false)]
+async fn unprepared_level_is_not_found(pool: PgPool) {
+    let app = test_app(pool).await;
+    for uri in [
+        "/get-objects?version=7",
+        "/get-object?version=7&id=1",
+        "/count?version=7",
+        "/search?version=7&q=prop",
+        "/audit?version=7",
+    ] {
+        let (status, body) = get(&app, uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+        assert_eq!(body["error"], "level version 7 does not exist", "{}", uri);
+    }
+    let (status, _) = post(&app, "/set-object", new_object("7", "prop", 0.0)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = false)]
+async fn object_type_defaults_when_left_out(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+
+    let mut body = new_object("1", "prop", 0.0);
+    body.as_object_mut().unwrap().remove("object_type");
+    let (status, body) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["object"]["object_type"], "prop");
+
+    let object = set_object(&app, "1", "light", 0.0).await;
+    assert_eq!(object["object_type"], "light");
+}
+
+#[sqlx::test(migrations = false)]
+async fn object_types_come_from_the_config(pool: PgPool) {
+    let app = app_with(
+        pool,
+        &[
+            ("OBJECT_TYPES", "tree,rock"),
+            ("DEFAULT_OBJECT_TYPE", "rock"),
+        ],
+    )
+    .await;
+    prepare(&app, "1").await;
+
+    let mut body = new_object("1", "tree", 0.0);
+    body.as_object_mut().unwrap().remove("object_type");
+    let (status, body) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["object"]["object_type"], "rock");
+
+    set_object(&app, "1", "tree", 0.0).await;
+    let (status, _) = post(&app, "/set-object", new_object("1", "prop", 0.0)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn timestamps_are_set_on_insert(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let object = set_object(&app, "1", "prop", 0.0).await;
+    assert!(object["created_at"].is_string());
+    assert_eq!(object["created_at"], object["updated_at"]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn malformed_transforms_name_the_field(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for (field, value) in [
+        ("position", "abc"),
+        ("rotation", "0,0,1"),
+        ("scale", "1,1,1,1"),
+    ] {
+        let mut body = new_object("1", "prop", 0.0);
+        body[field] = json!(value);
+        let (status, body) = post(&app, "/set-object", body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", field);
+        assert_eq!(
+            body["errors"][0]["field"],
+            format!("/{}", field),
+            "{}",
+            body
+        );
+    }
+
+    let object = |position: &str| {
+        json!({
+            "object_type": "prop",
+            "position": position,
+            "rotation": "0,0,0,1",
+            "scale": "1,1,1",
+            "collider": "null",
+        })
+    };
+    let (status, body) = post(
+        &app,
+        "/set-objects",
+        json!({"version": "1", "objects": [object("0,0,0"), object("1,2")]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(body["errors"][0]["field"], "/objects/1/position");
+    assert!(object_ids(&app, "1").await.is_empty());
+}
+
+#[sqlx::test(migrations = false)]
+async fn invalid_fields_are_listed(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+
+    let (status, body) = post(
+        &app,
+        "/set-object",
+        json!({
+            "version": "1",
+            "object_type": "spaceship",
+            "position": "1e999,0,0",
+            "rotation": "0,0,0,1",
+            "scale": "1,1,1",
+            "collider": "{not json",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["object_type", "position", "collider"]);
+    assert!(body["error"].is_string());
+
+    let (status, _) = post(&app, "/set-object", new_object("1", "pr\u{7}op", 0.0)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/set-object", new_object("1", "spaceship", 0.0)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn colliders_are_stored_as_typed_json(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for collider in [
+        json!({"type": "box", "size": {"x": 1.0, "y": 2.0, "z": 3.0}}),
+        json!({"type": "sphere", "radius": 0.5}),
+        json!({"type": "capsule", "radius": 0.5, "height": 2.0}),
+        json!({"type": "mesh", "mesh": "rock_01", "convex": true}),
+    ] {
+        let mut body = new_object("1", "collider", 0.0);
+        body["collider"] = json!(collider.to_string());
+        let (status, body) = post(&app, "/set-object", body).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (_, body) = get(&app, &format!("/get-object?version=1&id={}", body["count"])).await;
+        assert_eq!(body["collider"], collider);
+    }
+
+    let mut body = new_object("1", "collider", 0.0);
+    body["collider"] = json!(r#"{"type": "box", "size": {"x": 0, "y": 1, "z": 1}}"#);
+    let (status, _) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn set_object_with_an_id_inserts_or_overwrites(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+
+    let mut body = new_object("1", "prop", 0.0);
+    body["id"] = json!(10);
+    let (status, body) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(object_ids(&app, "1").await, [10]);
+
+    let mut body = new_object("1", "light", 5.0);
+    body["id"] = json!(10);
+    let (status, body) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 1);
+    let (_, body) = get(&app, "/get-object?version=1&id=10").await;
+    assert_eq!(body["object_type"], "light");
+
+    // The sequence moved past the explicit id.
+    let object = set_object(&app, "1", "prop", 0.0).await;
+    assert_eq!(object["id"], 11);
+}
+
+#[sqlx::test(migrations = false)]
+async fn set_objects_inserts_a_whole_batch(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let objects: Vec<_> = (0..500)
+        .map(|i| {
+            json!({
+                "object_type": "prop",
+                "position": format!("{},0,0", i),
+                "rotation": "0,0,0,1",
+                "scale": "1,1,1",
+                "collider": "null",
+            })
+        })
+        .collect();
+    let (status, body) = post(
+        &app,
+        "/set-objects",
+        json!({"version": "1", "objects": objects}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 500);
+
+    let (_, body) = get(&app, "/count?version=1").await;
+    assert_eq!(body["count"], 500);
+}
+
+#[sqlx::test(migrations = false)]
+async fn get_objects_pages_with_offsets(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..5 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+
+    let (_, first) = get(&app, "/get-objects?version=1&limit=2").await;
+    let (_, middle) = get(&app, "/get-objects?version=1&limit=2&offset=2").await;
+    let (_, beyond) = get(&app, "/get-objects?version=1&limit=2&offset=10").await;
+    let xs = |page: &Value| -> Vec<f64> {
+        page["objects"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["position"]["x"].as_f64().unwrap())
+            .collect()
+    };
+    assert_eq!(xs(&first), [0.0, 1.0]);
+    assert_eq!(xs(&middle), [2.0, 3.0]);
+    assert!(xs(&beyond).is_empty());
+    assert_eq!(beyond["total"], 5);
+
+    let (status, _) = get(&app, "/get-objects?version=1&limit=0").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get(&app, "/get-objects?version=1&offset=-1").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn cursor_pages_neither_skip_nor_repeat(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..4 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+
+    let mut seen = Vec::new();
+    let (_, page) = get(&app, "/get-objects?version=1&limit=2").await;
+    for object in page["objects"].as_array().unwrap() {
+        seen.push(object["id"].as_i64().unwrap());
+    }
+    // Added between pages, after the cursor.
+    set_object(&app, "1", "prop", 9.0).await;
+    let mut cursor = page["next_cursor"].clone();
+    while !cursor.is_null() {
+        let (_, page) = get(
+            &app,
+            &format!("/get-objects?version=1&limit=2&cursor={}", cursor),
+        )
+        .await;
+        for object in page["objects"].as_array().unwrap() {
+            seen.push(object["id"].as_i64().unwrap());
+        }
+        cursor = page["next_cursor"].clone();
+    }
+    assert_eq!(seen, [1, 2, 3, 4, 5]);
+}
+
+#[sqlx::test(migrations = false)]
+async fn get_objects_filters_by_type_and_tag(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "light", 1.0).await;
+    set_object(&app, "1", "prop", 2.0).await;
+    let mut tagged = new_object("1", "light", 3.0);
+    tagged["tags"] = json!(["red", "boss"]);
+    post(&app, "/set-object", tagged).await;
+
+    let (_, body) = get(&app, "/get-objects?version=1&object_type=prop").await;
+    assert_eq!(body["total"], 2);
+    assert!(body["objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|o| o["object_type"] == "prop"));
+
+    let (_, body) = get(&app, "/get-objects?version=1&tag=boss").await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["objects"][0]["tags"], json!(["red", "boss"]));
+
+    let (_, body) = get(&app, "/get-objects?version=1&object_type=prop&tag=boss").await;
+    assert_eq!(body["total"], 0);
+}
+
+#[sqlx::test(migrations = false)]
+async fn get_objects_by_ids_skips_missing_ids(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..3 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+    let (status, body) = post(
+        &app,
+        "/get-objects-by-ids",
+        json!({"version": "1", "ids": [3, 99, 1]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["objects"][0]["id"], 3);
+    assert_eq!(body["objects"][1]["id"], 1);
+}
+
+#[sqlx::test(migrations = false)]
+async fn search_matches_parts_of_types_and_tags(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let mut tagged = new_object("1", "prop", 0.0);
+    tagged["tags"] = json!(["Treasure_Chest"]);
+    post(&app, "/set-object", tagged).await;
+    set_object(&app, "1", "checkpoint", 1.0).await;
+
+    let (_, body) = get(&app, "/search?version=1&q=chest").await;
+    assert_eq!(body["total"], 1);
+    let (_, body) = get(&app, "/search?version=1&q=POINT").await;
+    assert_eq!(body["objects"][0]["object_type"], "checkpoint");
+    let (_, body) = get(&app, "/search?version=1&q=dragon").await;
+    assert_eq!(body["total"], 0);
+    // `_` is matched literally rather than as any character.
+    let (_, body) = get(&app, "/search?version=1&q=e_c").await;
+    assert_eq!(body["total"], 1);
+    let (_, body) = get(&app, "/search?version=1&q=p_o").await;
+    assert_eq!(body["total"], 0);
+}
+
+#[test]
+fn like_pattern_escapes_wildcards() {
+    assert_eq!(crate::like_pattern("chest"), "%chest%");
+    assert_eq!(crate::like_pattern("50%_off"), "%50\\%\\_off%");
+    assert_eq!(crate::like_pattern("a\\b"), "%a\\\\b%");
+}
+
+#[sqlx::test(migrations = false)]
+async fn unchanged_page_is_not_modified(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 0.0).await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/get-objects?version=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let etag = response.headers()[header::ETAG].clone();
+
+    let request = || {
+        Request::get("/get-objects?version=1")
+            .header(header::IF_NONE_MATCH, etag.clone())
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (status, _) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+    set_object(&app, "1", "prop", 1.0).await;
+    let (status, _) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = false)]
+async fn ndjson_streams_one_object_per_line(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..3 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/get-objects?version=1")
+                .header(header::ACCEPT, "application/x-ndjson")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/x-ndjson"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let lines: Vec<LevelObject> = body
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+}
+
+#[sqlx::test(migrations = false)]
+async fn objects_round_trip_through_msgpack(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+
+    let body = rmp_serde::to_vec_named(&new_object("1", "prop", 4.0)).unwrap();
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/set-object")
+                .header(header::CONTENT_TYPE, "application/msgpack")
+                .header(header::ACCEPT, "application/msgpack")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/msgpack"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let stored: level_server::api::SetObjectsResponse = rmp_serde::from_slice(&body).unwrap();
+    let object = stored.object.unwrap();
+    assert_eq!(object.position.x, 4.0);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/get-objects?version=1")
+                .header(header::ACCEPT, "application/msgpack")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page: level_server::api::GetObjectsResponse = rmp_serde::from_slice(&body).unwrap();
+    assert_eq!(page.objects[0].id, object.id);
+    assert_eq!(page.objects[0].created_at, object.created_at);
+}
+
+#[sqlx::test(migrations = false)]
+async fn box_query_keeps_objects_inside(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    set_object(&app, "1", "prop", 1.0).await;
+    set_object(&app, "1", "prop", 5.0).await;
+    set_object(&app, "1", "prop", 20.0).await;
+
+    let (_, body) = get(
+        &app,
+        "/get-objects-in-box?version=1&min_x=0&min_y=0&min_z=0&max_x=5&max_y=1&max_z=1",
+    )
+    .await;
+    assert_eq!(body["total"], 2);
+    let (status, _) = get(
+        &app,
+        "/get-objects-in-box?version=1&min_x=5&min_y=0&min_z=0&max_x=0&max_y=1&max_z=1",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn first_and_last_ids(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let (status, _) = get(&app, "/get-first?version=1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get(&app, "/get-last?version=1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    for x in 0..3 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+    let (_, body) = get(&app, "/get-first?version=1").await;
+    assert_eq!(body["id"], 1);
+    let (_, body) = get(&app, "/get-last?version=1").await;
+    assert_eq!(body["id"], 3);
+}
+
+#[sqlx::test(migrations = false)]
+async fn objects_since_a_time(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let before = set_object(&app, "1", "prop", 0.0).await;
+    let doomed = set_object(&app, "1", "prop", 1.0).await;
+    let gone = set_object(&app, "1", "prop", 3.0).await;
+    let cutoff = before["updated_at"].as_str().unwrap().to_string();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let after = set_object(&app, "1", "light", 2.0).await;
+    delete(
+        &app,
+        &format!("/delete-object?version=1&id={}", doomed["id"]),
+    )
+    .await;
+    delete(
+        &app,
+        &format!("/delete-object?version=1&id={}&hard=true", gone["id"]),
+    )
+    .await;
+
+    let (status, body) = get(
+        &app,
+        &format!(
+            "/get-objects-since?version=1&since={}",
+            cutoff.replace('+', "%2B")
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let ids: Vec<_> = body["objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| o["id"].clone())
+        .collect();
+    assert!(ids.contains(&after["id"]));
+    assert!(!ids.contains(&before["id"]));
+    assert_eq!(body["deleted_ids"], json!([doomed["id"], gone["id"]]));
+
+    let (status, _) = get(&app, "/get-objects-since?version=1&since=yesterday").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = false)]
+async fn counts_across_versions(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    prepare(&app, "2").await;
+    set_object(&app, "1", "prop", 0.0).await;
+    set_object(&app, "1", "prop", 1.0).await;
+
+    let (status, body) = post(&app, "/get-counts", json!({"versions": ["1", "2", "3"]})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["counts"], json!({"1": 2, "2": 0, "3": null}));
+
+    let (status, body) = post(&app, "/get-counts", json!({"versions": ["1", "x"]})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["errors"][0]["field"], "versions[1]");
+}
+
+#[sqlx::test(migrations = false)]
+async fn every_write_is_published(pool: PgPool) {
+    migrate(&pool).await;
+    let environments = Environments::new(pool, BTreeMap::new());
+    let mut events = environments.get(None).unwrap().events.subscribe();
+    let config = Config::from_vars(std::iter::empty()).unwrap();
+    let app = app(environments, &config).unwrap();
+
+    let object = json!({
+        "object_type": "prop",
+        "position": "0,0,0",
+        "rotation": "0,0,0,1",
+        "scale": "1,1,1",
+        "collider": "null",
+    });
+    let (status, body) = get(&app, "/prepare?version=1").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let writes = [
+        (
+            Method::POST,
+            "/set-objects",
+            json!({"version": "1", "objects": [object, object]}),
+        ),
+        (
+            Method::POST,
+            "/delete-objects",
+            json!({"version": "1", "ids": [1, 99]}),
+        ),
+        (
+            Method::POST,
+            "/restore-object",
+            json!({"version": "1", "id": 1}),
+        ),
+        (Method::POST, "/snapshot?version=1", Value::Null),
+        (Method::POST, "/clear-level?version=1", Value::Null),
+        (
+            Method::POST,
+            "/restore-snapshot",
+            json!({"version": "1", "snapshot_id": 1}),
+        ),
+        (
+            Method::POST,
+            "/import-level",
+            json!({"version": "1", "objects": [object]}),
+        ),
+        (
+            Method::POST,
+            "/copy-level",
+            json!({"from_version": "1", "to_version": "2"}),
+        ),
+    ];
+    for (method, uri, body) in writes {
+        let (status, body) = send(&app, json_request(method, uri, &body)).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", uri, body);
+    }
+
+    let mut published = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        let event = serde_json::to_value(event).unwrap();
+        published.push((
+            event["op"].as_str().unwrap().to_string(),
+            event["version"].as_str().unwrap().to_string(),
+        ));
+    }
+    let expected = [
+        ("reload", "1"),
+        ("set", "1"),
+        ("set", "1"),
+        ("delete", "1"),
+        ("set", "1"),
+        ("reload", "1"),
+        ("reload", "1"),
+        ("reload", "1"),
+        ("reload", "2"),
+    ];
+    assert_eq!(
+        published,
+        expected.map(|(op, version)| (op.to_string(), version.to_string()))
+    );
+}
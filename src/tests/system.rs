@@ -0,0 +1,376 @@
+use std::{
+    io::Write,
+    net::SocketAddr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::json;
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    PgPool,
+};
+
+use super::*;
+
+#[sqlx::test(migrations = false)]
+async fn health_reports_the_pool(pool: PgPool) {
+    let app = test_app(pool.clone()).await;
+    for uri in ["/health", "/readyz"] {
+        let (status, body) = get(&app, uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+        for field in ["size", "idle", "max_connections"] {
+            assert!(body["pool"][field].is_u64(), "{} {}", uri, field);
+        }
+    }
+
+    pool.close().await;
+    let (status, body) = get(&app, "/health").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+    // Liveness doesn't depend on the database.
+    let (status, body) = get(&app, "/livez").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("pool").is_none());
+}
+
+#[sqlx::test(migrations = false)]
+async fn migrations_are_reported_applied(pool: PgPool) {
+    let app = test_app(pool).await;
+    let (status, body) = get(&app, "/migrations").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["pending"], json!([]));
+    assert_eq!(
+        body["applied"].as_array().unwrap().len(),
+        crate::MIGRATOR
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+            .count()
+    );
+}
+
+#[sqlx::test(migrations = false)]
+async fn migrations_revert_and_reapply(pool: PgPool) {
+    let app = test_app(pool.clone()).await;
+    let first = crate::MIGRATOR.iter().next().unwrap().version;
+    crate::MIGRATOR.undo(&pool, first).await.unwrap();
+
+    let (_, body) = get(&app, "/migrations").await;
+    assert_eq!(body["applied"].as_array().unwrap().len(), 1);
+    assert!(!body["pending"].as_array().unwrap().is_empty());
+    for table in ["level_metadata", "idempotency"] {
+        let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(table)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(!exists, "{} was not dropped", table);
+    }
+
+    crate::MIGRATOR.run(&pool).await.unwrap();
+    let (_, body) = get(&app, "/migrations").await;
+    assert_eq!(body["pending"], json!([]));
+}
+
+#[tokio::test]
+async fn unreachable_database_gives_up_after_retrying() {
+    let options = PgConnectOptions::from_str("postgres://postgres@127.0.0.1:1/none").unwrap();
+    let started = Instant::now();
+    let result = crate::connect_with_retry(
+        PgPoolOptions::new().acquire_timeout(Duration::from_secs(1)),
+        options,
+        2,
+    )
+    .await;
+    assert!(result.is_err());
+    // One backoff between the two attempts.
+    assert!(started.elapsed() >= crate::CONNECT_INITIAL_BACKOFF);
+}
+
+#[sqlx::test(migrations = false)]
+async fn api_key_protects_writes(pool: PgPool) {
+    let app = app_with(pool, &[("API_KEY", "secret")]).await;
+    let prepare = |key: Option<&str>| {
+        let mut request = Request::get("/prepare?version=1");
+        if let Some(key) = key {
+            request = request.header("x-api-key", key);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let (status, _) = send(&app, prepare(None)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, prepare(Some("guess"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, prepare(Some("secret"))).await;
+    assert_eq!(status, StatusCode::OK);
+    // Reads stay open unless API_KEY_PROTECT_READS is set.
+    let (status, _) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = false)]
+async fn read_only_mode_blocks_writes(pool: PgPool) {
+    prepare(&test_app(pool.clone()).await, "1").await;
+    let app = app_with(pool, &[("READ_ONLY", "true")]).await;
+
+    let (status, _) = post(&app, "/set-object", new_object("1", "prop", 0.0)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let (status, _) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = false)]
+async fn bursts_past_the_rate_limit_are_rejected(pool: PgPool) {
+    let app = app_with(pool, &[("WRITE_RATE_LIMIT", "2")]).await;
+    let peer = SocketAddr::from(([10, 0, 0, 1], 4000));
+    let request = || {
+        let mut request = Request::get("/prepare?version=1")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    };
+
+    let (status, _) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(&app, request()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(body["error"].as_str().unwrap().contains("rate limit"));
+    // Reads aren't limited.
+    let (status, _) = get(&app, "/versions").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = false)]
+async fn oversized_bodies_are_rejected(pool: PgPool) {
+    let app = app_with(pool, &[("MAX_REQUEST_BODY_BYTES", "256")]).await;
+    prepare(&app, "1").await;
+    let mut body = new_object("1", "prop", 0.0);
+    body["tags"] = json!(vec!["x".repeat(50); 10]);
+    let (status, _) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+fn gzip(body: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[sqlx::test(migrations = false)]
+async fn gzipped_request_bodies_are_accepted(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let body = new_object("1", "prop", 0.0).to_string();
+
+    let request = Request::post("/set-object")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(gzip(body.as_bytes())))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["count"], 1);
+
+    let request = Request::post("/set-object")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, "br")
+        .body(Body::from("whatever"))
+        .unwrap();
+    let (status, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[sqlx::test(migrations = false)]
+async fn large_responses_are_gzipped(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    for x in 0..20 {
+        set_object(&app, "1", "prop", f64::from(x)).await;
+    }
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/get-objects?version=1")
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+}
+
+#[sqlx::test(migrations = false)]
+async fn responses_carry_a_request_id(pool: PgPool) {
+    let app = test_app(pool).await;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/get-object?version=x;&id=1")
+                .header("x-request-id", "trace-me")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "trace-me");
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["request_id"], "trace-me");
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/livez").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert!(!response.headers()["x-request-id"].is_empty());
+}
+
+#[sqlx::test(migrations = false)]
+async fn schema_violations_are_unprocessable(pool: PgPool) {
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let mut body = new_object("1", "prop", 0.0);
+    body["colour"] = json!("red");
+    let (status, body) = post(&app, "/set-object", body).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!body["errors"].as_array().unwrap().is_empty());
+}
+
+#[sqlx::test(migrations = false)]
+async fn openapi_spec_describes_the_routes(pool: PgPool) {
+    let app = test_app(pool).await;
+    let (status, spec) = get(&app, "/api-docs/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    for path in ["/get-objects", "/set-object", "/create-level", "/health"] {
+        assert!(spec["paths"][path].is_object(), "{}", path);
+    }
+}
+
+#[sqlx::test(migrations = false)]
+async fn slow_queries_time_out(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let pool = pool_options
+        .clone()
+        .connect_with(connect_options.clone())
+        .await?;
+    prepare(&test_app(pool.clone()).await, "1").await;
+
+    let slow_pool = pool_options
+        .connect_with(connect_options.options([("statement_timeout", "200ms")]))
+        .await?;
+    let app = test_app(slow_pool).await;
+
+    // Holding the table makes the read wait until the timeout ends it.
+    let mut tx = pool.begin().await?;
+    sqlx::query("LOCK TABLE objects_v1 IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    let started = Instant::now();
+    let (status, body) = get(&app, "/get-objects?version=1").await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["error"], "query timed out");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    tx.rollback().await?;
+    Ok(())
+}
+
+#[sqlx::test(migrations = false)]
+async fn env_parameter_selects_the_database(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let primary = pool_options
+        .clone()
+        .connect_with(connect_options.clone())
+        .await?;
+    sqlx::query("CREATE SCHEMA staging")
+        .execute(&primary)
+        .await?;
+    let staging = pool_options
+        .connect_with(connect_options.options([("search_path", "staging")]))
+        .await?;
+    let config = Config::from_vars(std::iter::empty()).unwrap();
+    let environments = Environments::new(
+        primary,
+        [("staging".to_string(), staging)].into_iter().collect(),
+    );
+    let app = app(environments, &config).unwrap();
+
+    let (status, _) = get(&app, "/prepare?version=1&env=staging").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = get(&app, "/versions?env=staging").await;
+    assert_eq!(body["versions"], json!(["1"]));
+    let (_, body) = get(&app, "/versions").await;
+    assert_eq!(body["versions"], json!([]));
+    let (status, _) = get(&app, "/versions?env=production").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+#[sqlx::test(migrations = false)]
+async fn client_talks_to_a_running_server(pool: PgPool) {
+    use level_server::{
+        api::{PatchLevelObjectRequest, SetLevelObjectRequest},
+        client::{Client, ClientError},
+    };
+
+    let app = test_app(pool).await;
+    prepare(&app, "1").await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(std::future::IntoFuture::into_future(axum::serve(
+        listener, app,
+    )));
+    let client = Client::new(format!("http://{}", addr));
+
+    let stored = client
+        .set_object(&SetLevelObjectRequest {
+            version: "1".to_string(),
+            id: None,
+            object_type: Some("prop".to_string()),
+            position: "1,2,3".to_string(),
+            rotation: "0,0,0,1".to_string(),
+            scale: "1,1,1".to_string(),
+            collider: "null".to_string(),
+            tags: Vec::new(),
+            editor: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(stored.count, 1);
+    assert_eq!(client.count("1").await.unwrap(), 1);
+    assert_eq!(client.versions().await.unwrap(), ["1"]);
+
+    let patched = client
+        .patch_object(&PatchLevelObjectRequest {
+            version: "1".to_string(),
+            id: 1,
+            scale: Some("2,2,2".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(patched.scale.x, 2.0);
+    assert_eq!(client.get_objects("1").await.unwrap().len(), 1);
+
+    client.delete_object("1", 1).await.unwrap();
+    match client.get_object("1", 1).await {
+        Err(ClientError::Api { status, .. }) => assert_eq!(status, StatusCode::NOT_FOUND),
+        other => panic!("expected a 404, got {:?}", other.map(|o| o.id)),
+    }
+}
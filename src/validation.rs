@@ -0,0 +1,370 @@
+use std::{str::FromStr, sync::Arc};
+
+use level_server::{
+    api::{FieldError, NewLevelObject},
+    geometry::{Collider, ParseVectorError, Quat, Transform, Vec3},
+};
+
+/// Object types accepted when `OBJECT_TYPES` isn't set.
+pub const DEFAULT_OBJECT_TYPES: &[&str] = &[
+    "spawn",
+    "prop",
+    "collider",
+    "trigger",
+    "light",
+    "checkpoint",
+];
+
+/// Type given to objects sent without one when `DEFAULT_OBJECT_TYPE` isn't
+/// set.
+pub const DEFAULT_OBJECT_TYPE: &str = "prop";
+
+/// Length of the `object_type` column.
+const MAX_OBJECT_TYPE_LEN: usize = 255;
+
+/// The object types clients may store, from `OBJECT_TYPES` so new types don't
+/// need a release, and the one given to objects sent to `/set-object` without
+/// a type.
+#[derive(Debug, Clone)]
+pub struct ObjectTypes {
+    allowed: Arc<[String]>,
+    default: Arc<str>,
+}
+
+impl ObjectTypes {
+    /// Fails when `default` isn't one of the `allowed` types.
+    pub fn new(allowed: Vec<String>, default: String) -> Result<Self, String> {
+        if !allowed.contains(&default) {
+            return Err(format!(
+                "DEFAULT_OBJECT_TYPE {:?} must be one of OBJECT_TYPES: {}",
+                default,
+                allowed.join(", ")
+            ));
+        }
+        Ok(ObjectTypes {
+            allowed: allowed.into(),
+            default: default.into(),
+        })
+    }
+
+    pub fn default_type(&self) -> &str {
+        &self.default
+    }
+
+    pub fn validate(&self, object_type: &str) -> Result<(), FieldError> {
+        let len = object_type.chars().count();
+        if len > MAX_OBJECT_TYPE_LEN {
+            return Err(FieldError::new(
+                "object_type",
+                format!(
+                    "must be at most {} characters, got {}",
+                    MAX_OBJECT_TYPE_LEN, len
+                ),
+            ));
+        }
+        if let Some(c) = object_type.chars().find(|c| c.is_control()) {
+            return Err(FieldError::new(
+                "object_type",
+                format!("must not contain control characters, found {:?}", c),
+            ));
+        }
+        if self.allowed.iter().any(|t| t == object_type) {
+            Ok(())
+        } else {
+            Err(FieldError::new(
+                "object_type",
+                format!(
+                    "unknown type {:?}, expected one of: {}",
+                    object_type,
+                    self.allowed.join(", ")
+                ),
+            ))
+        }
+    }
+}
+
+impl Default for ObjectTypes {
+    fn default() -> Self {
+        ObjectTypes {
+            allowed: DEFAULT_OBJECT_TYPES.iter().map(|t| t.to_string()).collect(),
+            default: DEFAULT_OBJECT_TYPE.into(),
+        }
+    }
+}
+
+/// Parses one text vector field of a request, naming it if it's invalid.
+pub fn parse_field<T>(field: &str, value: &str) -> Result<T, FieldError>
+where
+    T: FromStr<Err = ParseVectorError>,
+{
+    value
+        .parse()
+        .map_err(|e: ParseVectorError| FieldError::new(field, e.to_string()))
+}
+
+/// Rejects scales Unity's physics can't handle: every component must be
+/// positive.
+pub fn validate_scale(field: &str, scale: &Vec3) -> Result<(), FieldError> {
+    if [scale.x, scale.y, scale.z].iter().all(|c| *c > 0.0) {
+        Ok(())
+    } else {
+        Err(FieldError::new(
+            field,
+            format!("every component must be greater than 0, got {}", scale),
+        ))
+    }
+}
+
+/// Parses the collider JSON sent by a client. `null` means the object has no
+/// collider.
+pub fn parse_collider(collider: &str) -> Result<Option<Collider>, FieldError> {
+    let collider: Option<Collider> =
+        serde_json::from_str(collider).map_err(|e| FieldError::new("collider", e.to_string()))?;
+    match collider {
+        Some(collider) if !collider.has_positive_dimensions() => Err(FieldError::new(
+            "collider",
+            "dimensions must be greater than 0",
+        )),
+        collider => Ok(collider),
+    }
+}
+
+/// The validated form of an object sent by a client.
+pub struct ParsedObject {
+    pub transform: Transform,
+    pub collider: Option<Collider>,
+}
+
+/// Validates every field of an object, reporting all invalid fields together
+/// so a client can highlight each of them.
+pub fn parse_object(
+    types: &ObjectTypes,
+    object_type: &str,
+    position: &str,
+    rotation: &str,
+    scale: &str,
+    collider: &str,
+) -> Result<ParsedObject, Vec<FieldError>> {
+    let object_type = types.validate(object_type);
+    let position = parse_field("position", position);
+    let rotation = parse_field("rotation", rotation);
+    let scale = parse_field("scale", scale);
+    let collider = parse_collider(collider);
+    match (object_type, position, rotation, scale, collider) {
+        (Ok(()), Ok(position), Ok(rotation), Ok(scale), Ok(collider)) => Ok(ParsedObject {
+            transform: Transform {
+                position,
+                rotation,
+                scale,
+            },
+            collider,
+        }),
+        (object_type, position, rotation, scale, collider) => Err([
+            object_type.err(),
+            position.err(),
+            rotation.err(),
+            scale.err(),
+            collider.err(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()),
+    }
+}
+
+/// The validated fields of a partial update; `None` leaves a field as it is.
+pub struct ParsedPatch {
+    pub object_type: Option<String>,
+    pub position: Option<Vec3>,
+    pub rotation: Option<Quat>,
+    pub scale: Option<Vec3>,
+    pub collider: Option<Option<Collider>>,
+}
+
+/// Validates the fields present in a partial update, reporting all invalid
+/// ones together.
+pub fn parse_patch(
+    types: &ObjectTypes,
+    object_type: Option<String>,
+    position: Option<&str>,
+    rotation: Option<&str>,
+    scale: Option<&str>,
+    collider: Option<&str>,
+) -> Result<ParsedPatch, Vec<FieldError>> {
+    fn check<T>(errors: &mut Vec<FieldError>, result: Result<T, FieldError>) -> Option<T> {
+        result.map_err(|e| errors.push(e)).ok()
+    }
+
+    let mut errors = Vec::new();
+    if let Some(object_type) = &object_type {
+        check(&mut errors, types.validate(object_type));
+    }
+    let position = position.and_then(|p| check(&mut errors, parse_field("position", p)));
+    let rotation = rotation.and_then(|r| check(&mut errors, parse_field("rotation", r)));
+    let scale = scale.and_then(|s| check(&mut errors, parse_field("scale", s)));
+    let collider = collider.and_then(|c| check(&mut errors, parse_collider(c)));
+    if errors.is_empty() {
+        Ok(ParsedPatch {
+            object_type,
+            position,
+            rotation,
+            scale,
+            collider,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Validates a batch of objects, naming each invalid field by its position in
+/// the batch, e.g. `objects[3].scale`.
+pub fn parse_objects(
+    types: &ObjectTypes,
+    objects: &[NewLevelObject],
+) -> Result<Vec<ParsedObject>, Vec<FieldError>> {
+    let mut parsed = Vec::with_capacity(objects.len());
+    let mut errors = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        match parse_object(
+            types,
+            &object.object_type,
+            &object.position,
+            &object.rotation,
+            &object.scale,
+            &object.collider,
+        ) {
+            Ok(object) => parsed.push(object),
+            Err(object_errors) => errors.extend(object_errors.into_iter().map(|e| FieldError {
+                field: format!("objects[{}].{}", index, e.field),
+                message: e.message,
+            })),
+        }
+    }
+    if errors.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_type_must_be_known() {
+        let types = ObjectTypes::default();
+        assert!(types.validate("prop").is_ok());
+        let err = types.validate("spaceship").unwrap_err();
+        assert_eq!(err.field, "object_type");
+        assert!(types.validate("pr\nop").is_err());
+        assert!(types
+            .validate(&"a".repeat(MAX_OBJECT_TYPE_LEN + 1))
+            .is_err());
+    }
+
+    #[test]
+    fn default_type_must_be_allowed() {
+        let allowed = vec!["tree".to_string(), "rock".to_string()];
+        let types = ObjectTypes::new(allowed.clone(), "rock".to_string()).unwrap();
+        assert_eq!(types.default_type(), "rock");
+        assert!(types.validate("tree").is_ok());
+        assert!(types.validate("prop").is_err());
+        assert!(ObjectTypes::new(allowed, "prop".to_string()).is_err());
+    }
+
+    #[test]
+    fn scale_components_must_be_positive() {
+        assert!(validate_scale("scale", &"1,1,1".parse().unwrap()).is_ok());
+        assert!(validate_scale("scale", &"1,0,1".parse().unwrap()).is_err());
+        assert!(validate_scale("scale", &"1,1,-2".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn collider_null_means_none() {
+        assert_eq!(parse_collider("null").unwrap(), None);
+        assert_eq!(
+            parse_collider(r#"{"type": "sphere", "radius": 2}"#).unwrap(),
+            Some(Collider::Sphere { radius: 2.0 })
+        );
+        assert!(parse_collider(r#"{"type": "sphere", "radius": 0}"#).is_err());
+        assert!(parse_collider(r#"{"type": "cone"}"#).is_err());
+    }
+
+    #[test]
+    fn parse_object_reports_every_invalid_field() {
+        let parsed = parse_object(
+            &ObjectTypes::default(),
+            "prop",
+            "1,2,3",
+            "0,0,0,1",
+            "1,1,1",
+            "null",
+        )
+        .unwrap();
+        assert_eq!(parsed.transform.position.y, 2.0);
+        assert!(parsed.collider.is_none());
+
+        let errors = parse_object(
+            &ObjectTypes::default(),
+            "spaceship",
+            "1,2",
+            "0,0,0,1",
+            "1,1",
+            "{",
+        )
+        .err()
+        .unwrap();
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["object_type", "position", "scale", "collider"]);
+    }
+
+    #[test]
+    fn parse_patch_checks_only_present_fields() {
+        let patch = parse_patch(
+            &ObjectTypes::default(),
+            None,
+            Some("1,2,3"),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(patch.position.is_some());
+        assert!(patch.rotation.is_none() && patch.scale.is_none());
+
+        let errors = parse_patch(
+            &ObjectTypes::default(),
+            Some("spaceship".to_string()),
+            None,
+            Some("1"),
+            None,
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn parse_objects_names_fields_by_index() {
+        let object = |position: &str| NewLevelObject {
+            object_type: "prop".to_string(),
+            position: position.to_string(),
+            rotation: "0,0,0,1".to_string(),
+            scale: "1,1,1".to_string(),
+            collider: "null".to_string(),
+            tags: Vec::new(),
+        };
+        assert_eq!(
+            parse_objects(&ObjectTypes::default(), &[object("0,0,0")])
+                .unwrap()
+                .len(),
+            1
+        );
+        let errors = parse_objects(&ObjectTypes::default(), &[object("0,0,0"), object("0,0")])
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].field, "objects[1].position");
+    }
+}
@@ -0,0 +1,110 @@
+//! Deployments sharing a database through `TABLE_PREFIX`. The prefix is set
+//! once per process, so these tests live in their own binary.
+
+use level_server::queries::{
+    claim_idempotency_key_sql, delete_level_metadata_sql, expire_idempotency_keys_sql,
+    get_level_metadata_sql, set_level_metadata_sql, set_table_prefix,
+};
+use sqlx::{migrate::Migrator, PgPool, Row};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Switches to the `tenant_v` prefix and runs the migrations, with a
+/// stand-in for the `objects_v0` table the first one patches.
+async fn migrate(pool: &PgPool) {
+    set_table_prefix("tenant_v".to_string());
+    sqlx::query(
+        "CREATE TABLE objects_v0 (id SERIAL PRIMARY KEY, object_type INTEGER, color INTEGER, position TEXT, size TEXT)",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    MIGRATOR.run(pool).await.unwrap();
+}
+
+/// Names of the levels with metadata, per prefix.
+async fn metadata(pool: &PgPool) -> Vec<(String, String, String)> {
+    sqlx::query_as("SELECT table_prefix, version, name FROM level_metadata ORDER BY table_prefix")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = false)]
+async fn level_metadata_is_kept_per_prefix(pool: PgPool) {
+    migrate(&pool).await;
+    sqlx::query("INSERT INTO level_metadata (table_prefix, version, name) VALUES ('objects_v', '1', 'theirs')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    sqlx::query(&set_level_metadata_sql())
+        .bind("1")
+        .bind(Some("ours"))
+        .bind(None::<String>)
+        .bind(None::<String>)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let row = sqlx::query(&get_level_metadata_sql())
+        .bind("1")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<String, _>("name"), "ours");
+    assert_eq!(
+        metadata(&pool).await,
+        [
+            (
+                "objects_v".to_string(),
+                "1".to_string(),
+                "theirs".to_string()
+            ),
+            ("tenant_v".to_string(), "1".to_string(), "ours".to_string()),
+        ]
+    );
+
+    sqlx::query(&delete_level_metadata_sql("1".to_string()))
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        metadata(&pool).await,
+        [(
+            "objects_v".to_string(),
+            "1".to_string(),
+            "theirs".to_string()
+        )]
+    );
+}
+
+#[sqlx::test(migrations = false)]
+async fn idempotency_keys_are_kept_per_prefix(pool: PgPool) {
+    migrate(&pool).await;
+    sqlx::query("INSERT INTO idempotency (table_prefix, key, created_at) VALUES ('objects_v', 'k', now() - interval '1 day')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let claimed = sqlx::query(&claim_idempotency_key_sql())
+        .bind("k")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(claimed.rows_affected(), 1);
+
+    sqlx::query("UPDATE idempotency SET created_at = now() - interval '1 day'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(&expire_idempotency_keys_sql())
+        .bind(60.0)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let left: Vec<String> = sqlx::query_scalar("SELECT table_prefix FROM idempotency")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(left, ["objects_v"]);
+}